@@ -0,0 +1,448 @@
+//! Text assembly for the bytecode VM.
+//!
+//! Every line holds at most one instruction written as its mnemonic followed by operand, if the
+//! instruction takes one. Everything after `;` is a comment. Line can start with a label that
+//! jumps refer to instead of raw byte offsets.
+//!
+//! ```text
+//!         LITERAL 3
+//! loop:   DUP
+//!         LITERAL 0
+//!         GREATER
+//!         JUMP_IF_FALSE end
+//!         LITERAL 1
+//!         SUBTRACT
+//!         JUMP loop
+//! end:    POP
+//! ```
+//!
+//! Bytes that do not form a valid instruction can be written with `.byte` directive which is also
+//! what disassembler falls back to, so its output can always be assembled back.
+//!
+//! `.entry name` directive marks the following instruction as a start of named function when
+//! assembling into a `Module`.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use num::FromPrimitive;
+
+use bytecode::{read_u16, read_u32, write_u16, write_u32, Instruction};
+use bytecode::module::{Function, LineEntry, Module};
+
+/// Column at which disassembler starts its comments.
+const COMMENT_COLUMN: usize = 28;
+/// Indentation of instructions in disassembly, labels are not indented.
+const INDENT: usize = 4;
+
+
+#[derive(Debug, PartialEq)]
+pub enum AssembleErrorKind {
+    UnknownMnemonic(String),
+    MissingOperand(&'static str),
+    UnexpectedOperand(String),
+    InvalidOperand(String),
+    UnknownLabel(String),
+    /// Label is too far for the 16 bit operand of a jump.
+    JumpOutOfRange(String),
+    DuplicateLabel(String),
+    DuplicateEntry(String),
+}
+
+/// Error with the line (counting from 1) on which it happened.
+#[derive(Debug, PartialEq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub kind: AssembleErrorKind,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            AssembleErrorKind::UnknownMnemonic(ref m) => {
+                write!(f, "line {}: unknown mnemonic `{}`", self.line, m)
+            }
+            AssembleErrorKind::MissingOperand(m) => {
+                write!(f, "line {}: `{}` is missing its operand", self.line, m)
+            }
+            AssembleErrorKind::UnexpectedOperand(ref o) => {
+                write!(f, "line {}: unexpected operand `{}`", self.line, o)
+            }
+            AssembleErrorKind::InvalidOperand(ref o) => {
+                write!(f, "line {}: `{}` is not a valid operand", self.line, o)
+            }
+            AssembleErrorKind::UnknownLabel(ref l) => {
+                write!(f, "line {}: label `{}` is never defined", self.line, l)
+            }
+            AssembleErrorKind::JumpOutOfRange(ref l) => {
+                write!(f, "line {}: label `{}` is out of jump range", self.line, l)
+            }
+            AssembleErrorKind::DuplicateLabel(ref l) => {
+                write!(f, "line {}: label `{}` is already defined", self.line, l)
+            }
+            AssembleErrorKind::DuplicateEntry(ref e) => {
+                write!(f, "line {}: function `{}` is already defined", self.line, e)
+            }
+        }
+    }
+}
+
+
+/// Single line of assembly with its labels stripped.
+enum Item<'a> {
+    Byte(u8),
+    Instruction(Instruction, Option<&'a str>),
+}
+
+/// Turn assembly text into bytecode runnable by `VM`.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    assemble_module(source).map(|module| module.code)
+}
+
+/// Assemble text into a module with functions marked by `.entry` and a line for every
+/// instruction.
+pub fn assemble_module(source: &str) -> Result<Module, AssembleError> {
+    // First pass finds where every label points to.
+    let mut labels = HashMap::new();
+    let mut module = Module::default();
+    let mut items = Vec::new();
+    let mut offset = 0;
+    for (i, line) in source.lines().enumerate() {
+        let error = |kind| AssembleError { line: i + 1, kind: kind };
+
+        let code = line.split(';').next().unwrap_or("");
+        let mut tokens = code.split_whitespace().peekable();
+        while let Some(label) = tokens.peek().and_then(|t| label_name(t)) {
+            if labels.insert(label, offset).is_some() {
+                return Err(error(AssembleErrorKind::DuplicateLabel(label.to_owned())));
+            }
+            tokens.next();
+        }
+        let mnemonic = match tokens.next() {
+            Some(m) => m,
+            None => continue,
+        };
+        let operand = tokens.next();
+        if let Some(extra) = tokens.next() {
+            return Err(error(AssembleErrorKind::UnexpectedOperand(extra.to_owned())));
+        }
+
+        if mnemonic == ".entry" {
+            let name = operand
+                .ok_or_else(|| error(AssembleErrorKind::MissingOperand(".entry")))?;
+            if module.entry_point(name).is_some() {
+                return Err(error(AssembleErrorKind::DuplicateEntry(name.to_owned())));
+            }
+            module.functions.push(Function {
+                name: name.to_owned(),
+                offset: offset,
+            });
+            continue;
+        }
+        if mnemonic == ".byte" {
+            let operand = operand
+                .ok_or_else(|| error(AssembleErrorKind::MissingOperand(".byte")))?;
+            let byte = parse_byte(operand)
+                .ok_or_else(|| error(AssembleErrorKind::InvalidOperand(operand.to_owned())))?;
+            items.push((i + 1, Item::Byte(byte)));
+            offset += 1;
+            continue;
+        }
+
+        let instruction = Instruction::from_mnemonic(mnemonic)
+            .ok_or_else(|| error(AssembleErrorKind::UnknownMnemonic(mnemonic.to_owned())))?;
+        match (instruction.operand_len(), operand) {
+            (0, Some(o)) => return Err(error(AssembleErrorKind::UnexpectedOperand(o.to_owned()))),
+            (n, None) if n > 0 => {
+                return Err(error(AssembleErrorKind::MissingOperand(instruction.mnemonic())))
+            }
+            _ => {}
+        }
+        items.push((i + 1, Item::Instruction(instruction, operand)));
+        offset += 1 + instruction.operand_len();
+    }
+
+    // Second pass emits bytes with labels resolved.
+    let mut bytecode = Vec::with_capacity(offset);
+    for (line, item) in items {
+        let error = |kind| AssembleError { line: line, kind: kind };
+        module.lines.push(LineEntry {
+            offset: bytecode.len(),
+            line: line,
+        });
+        match item {
+            Item::Byte(byte) => bytecode.push(byte),
+            Item::Instruction(instruction, None) => bytecode.push(instruction as u8),
+            Item::Instruction(instruction, Some(operand)) => {
+                bytecode.push(instruction as u8);
+                let invalid = || error(AssembleErrorKind::InvalidOperand(operand.to_owned()));
+                match instruction {
+                    Instruction::LiteralI32 => {
+                        let value: i32 = operand.parse().map_err(|_| invalid())?;
+                        write_u32(&mut bytecode, value as u32);
+                    }
+                    Instruction::LiteralF32 => {
+                        let value: f32 = operand.parse().map_err(|_| invalid())?;
+                        write_u32(&mut bytecode, value.to_bits());
+                    }
+                    _ if instruction.operand_len() == 1 => {
+                        bytecode.push(parse_byte(operand).ok_or_else(&invalid)?);
+                    }
+                    _ => {
+                        let value: u16 = match labels.get(operand) {
+                            Some(&target) if instruction.is_jump() => {
+                                if target > u16::max_value() as usize {
+                                    let label = operand.to_owned();
+                                    return Err(error(AssembleErrorKind::JumpOutOfRange(label)));
+                                }
+                                target as u16
+                            }
+                            _ if operand.chars().all(|c| c.is_digit(10)) => {
+                                operand.parse().map_err(|_| invalid())?
+                            }
+                            _ if instruction.is_jump() => {
+                                let label = operand.to_owned();
+                                return Err(error(AssembleErrorKind::UnknownLabel(label)));
+                            }
+                            _ => return Err(invalid()),
+                        };
+                        write_u16(&mut bytecode, value);
+                    }
+                }
+            }
+        }
+    }
+    module.code = bytecode;
+    Ok(module)
+}
+
+/// Name of the label if token defines one.
+fn label_name(token: &str) -> Option<&str> {
+    if token.len() > 1 && token.ends_with(':') {
+        Some(&token[..token.len() - 1])
+    } else {
+        None
+    }
+}
+
+/// Accepts decimal and `0x` prefixed hexadecimal bytes.
+fn parse_byte(text: &str) -> Option<u8> {
+    if text.starts_with("0x") {
+        u8::from_str_radix(&text[2..], 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+
+/// Label disassembler gives to a jump target.
+fn label(target: usize) -> String {
+    format!("L{:04}", target)
+}
+
+/// Offsets of jump targets that land on a start of complete instruction or the end of bytecode.
+fn jump_targets(bytecode: &[u8]) -> BTreeSet<usize> {
+    let mut starts = BTreeSet::new();
+    let mut targets = BTreeSet::new();
+    let mut offset = 0;
+    while offset < bytecode.len() {
+        match Instruction::from_u8(bytecode[offset]) {
+            Some(instruction) if offset + instruction.operand_len() < bytecode.len() => {
+                starts.insert(offset);
+                if instruction.is_jump() {
+                    targets.insert(read_u16(bytecode, offset + 1) as usize);
+                }
+                offset += 1 + instruction.operand_len();
+            }
+            _ => offset += 1,
+        }
+    }
+    starts.insert(bytecode.len());
+    targets.intersection(&starts).cloned().collect()
+}
+
+/// Assembly text of a single instruction starting at offset, with jump targets as raw offsets.
+/// Bytes that aren't a complete instruction are shown as `.byte`.
+pub fn disassemble_instruction(bytecode: &[u8], offset: usize) -> String {
+    match Instruction::from_u8(bytecode[offset]) {
+        Some(instruction) if offset + instruction.operand_len() < bytecode.len() => {
+            instruction_text(bytecode, offset, instruction)
+        }
+        _ => format!(".byte {}", bytecode[offset]),
+    }
+}
+
+fn instruction_text(bytecode: &[u8], offset: usize, instruction: Instruction) -> String {
+    let operand = offset + 1;
+    match instruction {
+        _ if instruction.operand_len() == 0 => instruction.mnemonic().to_owned(),
+        _ if instruction.operand_len() == 1 => {
+            format!("{} {}", instruction.mnemonic(), bytecode[operand])
+        }
+        Instruction::LiteralI32 => {
+            format!("{} {}", instruction.mnemonic(), read_u32(bytecode, operand) as i32)
+        }
+        Instruction::LiteralF32 => {
+            let value = f32::from_bits(read_u32(bytecode, operand));
+            format!("{} {:?}", instruction.mnemonic(), value)
+        }
+        _ => format!("{} {}", instruction.mnemonic(), read_u16(bytecode, operand)),
+    }
+}
+
+/// Turn bytecode back into assembly text. Each line is commented with byte offset of the
+/// instruction and depth of the stack after it executes. Jump targets get labels.
+pub fn disassemble(bytecode: &[u8]) -> String {
+    let targets = jump_targets(bytecode);
+    // Depth of the stack at targets of jumps that were already seen.
+    let mut target_depths = HashMap::new();
+    // Unknown right after unconditional jump until we reach a label we know depth of.
+    let mut depth: Option<isize> = Some(0);
+
+    let mut out = String::new();
+    let mut offset = 0;
+    while offset <= bytecode.len() {
+        if targets.contains(&offset) {
+            out.push_str(&format!("{}:\n", label(offset)));
+            if depth.is_none() {
+                depth = target_depths.get(&offset).cloned();
+            }
+        }
+        if offset == bytecode.len() {
+            break;
+        }
+
+        let byte = bytecode[offset];
+        let (text, note, len) = match Instruction::from_u8(byte) {
+            Some(instruction) if offset + instruction.operand_len() < bytecode.len() => {
+                let (pops, pushes) = instruction.stack_effect();
+                depth = depth.map(|d| d - pops as isize + pushes as isize);
+                let text = if instruction.is_jump() {
+                    let target = read_u16(bytecode, offset + 1) as usize;
+                    if let Some(d) = depth {
+                        target_depths.entry(target).or_insert(d);
+                    }
+                    if targets.contains(&target) {
+                        format!("{} {}", instruction.mnemonic(), label(target))
+                    } else {
+                        format!("{} {}", instruction.mnemonic(), target)
+                    }
+                } else {
+                    instruction_text(bytecode, offset, instruction)
+                };
+                let note = match depth {
+                    Some(d) if d < 0 => format!("depth {} (underflow)", d),
+                    Some(d) => format!("depth {}", d),
+                    None => "depth ?".to_owned(),
+                };
+                if instruction == Instruction::Jump || instruction == Instruction::Halt {
+                    depth = None;
+                }
+                (text, note, 1 + instruction.operand_len())
+            }
+            Some(instruction) => {
+                (format!(".byte {}", byte), format!("truncated {}", instruction.mnemonic()), 1)
+            }
+            None => (format!(".byte {}", byte), "unknown opcode".to_owned(), 1),
+        };
+        out.push_str(&format!("{:indent$}{:<width$}; {:04}  {}\n",
+                              "",
+                              text,
+                              offset,
+                              note,
+                              indent = INDENT,
+                              width = COMMENT_COLUMN - INDENT));
+        offset += len;
+    }
+    out
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{assemble, disassemble, AssembleError, AssembleErrorKind};
+    use bytecode::VM;
+    use bytecode::host::WizardRoster;
+
+    #[test]
+    fn assembler() {
+        let source = "
+            ; set_health(0, get_health(0) + (get_agility(0) + get_wisdom(0)) / 2);
+            ENTITY 0
+            ENTITY 0
+            GET_HEALTH
+            ENTITY 0
+            GET_AGILITY
+            ENTITY 0
+            GET_WISDOM
+            ADD
+            LITERAL 2
+            DIVIDE
+            ADD
+            SET_HEALTH
+        ";
+        let bytecode = assemble(source).unwrap();
+        assert!(bytecode ==
+                vec![33, 0, 0, 33, 0, 0, 7, 33, 0, 0, 8, 33, 0, 0, 9, 10, 1, 2, 11, 10, 2]);
+
+        let text = disassemble(&bytecode);
+        assert!(text.lines().next().unwrap().ends_with("; 0000  depth 1"));
+        assert!(text.lines().last().unwrap().trim().starts_with("SET_HEALTH"));
+        assert!(assemble(&text).unwrap() == bytecode);
+
+        // Garbage still round trips through `.byte`.
+        let garbage = vec![255, 1];
+        assert!(assemble(&disassemble(&garbage)).unwrap() == garbage);
+
+        assert!(assemble("ADD\nLITERAL\n") ==
+                Err(AssembleError {
+            line: 2,
+            kind: AssembleErrorKind::MissingOperand("LITERAL"),
+        }));
+        assert!(assemble("\n\nJUMP_TO 3") ==
+                Err(AssembleError {
+            line: 3,
+            kind: AssembleErrorKind::UnknownMnemonic("JUMP_TO".to_owned()),
+        }));
+        let wide = assemble("LITERAL_I32 -2\nLITERAL_F32 0.5\nCONSTANT 258").unwrap();
+        assert!(wide == vec![30, 254, 255, 255, 255, 31, 0, 0, 0, 63, 32, 2, 1]);
+        assert!(disassemble(&wide).contains("LITERAL_F32 0.5 "));
+        assert!(assemble(&disassemble(&wide)).unwrap() == wide);
+
+        assert!(assemble("JUMP nowhere") ==
+                Err(AssembleError {
+            line: 1,
+            kind: AssembleErrorKind::UnknownLabel("nowhere".to_owned()),
+        }));
+        let far = format!("JUMP far\n{}far: POP", ".byte 0\n".repeat(70000));
+        assert!(assemble(&far) ==
+                Err(AssembleError {
+            line: 1,
+            kind: AssembleErrorKind::JumpOutOfRange("far".to_owned()),
+        }));
+    }
+
+    #[test]
+    fn labels() {
+        let source = "
+                    LITERAL 3
+            loop:   DUP
+                    LITERAL 0
+                    GREATER
+                    JUMP_IF_FALSE end
+                    LITERAL 1
+                    SUBTRACT
+                    JUMP loop
+            end:    POP
+        ";
+        let bytecode = assemble(source).unwrap();
+        assert!(bytecode == vec![1, 3, 25, 1, 0, 18, 24, 15, 0, 1, 1, 12, 23, 2, 0, 27]);
+        VM::new(bytecode.clone()).interpret(&mut WizardRoster::default()).unwrap();
+
+        let text = disassemble(&bytecode);
+        assert!(text.contains("L0002:\n"));
+        assert!(text.contains("JUMP_IF_FALSE L0015"));
+        assert!(text.contains("; 0015  depth 0"));
+        assert!(assemble(&text).unwrap() == bytecode);
+    }
+}
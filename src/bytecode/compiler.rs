@@ -0,0 +1,784 @@
+//! Spell expression compiler.
+//!
+//! Designers write spells as expressions calling wizard functions, which get compiled down into
+//! bytecode for the `VM`:
+//!
+//! ```text
+//! set_health(0, get_health(0) + (get_agility(0) + get_wisdom(0)) / 2)
+//! ```
+//!
+//! Multiple statements are separated with `;`. Value of every statement but the last is
+//! dropped, so only the last one leaves a value on the stack. Every function and operator maps
+//! directly to one `Instruction` and pure arithmetic on constants is folded at compile time.
+//! Comparisons give booleans, and logical operators and conditions take only booleans, so using
+//! a number where a boolean is expected fails at runtime with `VmError::TypeMismatch`.
+//!
+//! `wait(frames)` and `yield()` pause the spell when it is run one frame at a time with `VM::run`.
+
+use std::cmp;
+use std::fmt;
+
+use bytecode::{write_u16, write_u32, Instruction};
+use bytecode::module::{Function, LineEntry, Module};
+
+/// Deepest nesting the parser accepts, counting parentheses, calls and unary operators, as well
+/// as operators chained on one level, which nest in the syntax tree too.
+pub const MAX_NESTING: usize = 128;
+
+
+// ================================================================================================
+// Syntax tree
+// ================================================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    fn instruction(&self) -> Instruction {
+        match *self {
+            BinaryOp::Add => Instruction::Add,
+            BinaryOp::Subtract => Instruction::Subtract,
+            BinaryOp::Multiply => Instruction::Multiply,
+            BinaryOp::Divide => Instruction::Divide,
+            BinaryOp::Equal => Instruction::Equal,
+            BinaryOp::NotEqual => Instruction::NotEqual,
+            BinaryOp::Less => Instruction::Less,
+            BinaryOp::LessEqual => Instruction::LessEqual,
+            BinaryOp::Greater => Instruction::Greater,
+            BinaryOp::GreaterEqual => Instruction::GreaterEqual,
+            BinaryOp::And => Instruction::And,
+            BinaryOp::Or => Instruction::Or,
+        }
+    }
+
+    /// Evaluate operation on constants at compile time. Returns `None` when result would not
+    /// be known until run time or when types don't match, which is left for the `VM` to report.
+    fn fold(&self, lhs: &Expr, rhs: &Expr) -> Option<Expr> {
+        match (*self, lhs, rhs) {
+            (BinaryOp::Equal, &Expr::Bool(a), &Expr::Bool(b)) => Some(Expr::Bool(a == b)),
+            (BinaryOp::NotEqual, &Expr::Bool(a), &Expr::Bool(b)) => Some(Expr::Bool(a != b)),
+            (BinaryOp::And, &Expr::Bool(a), &Expr::Bool(b)) => Some(Expr::Bool(a && b)),
+            (BinaryOp::Or, &Expr::Bool(a), &Expr::Bool(b)) => Some(Expr::Bool(a || b)),
+            (op, &Expr::Number(a), &Expr::Number(b)) => {
+                match op {
+                    BinaryOp::Add => Some(Expr::Number(a + b)),
+                    BinaryOp::Subtract => Some(Expr::Number(a - b)),
+                    BinaryOp::Multiply => Some(Expr::Number(a * b)),
+                    BinaryOp::Divide if b != 0.0 => Some(Expr::Number(a / b)),
+                    BinaryOp::Equal => Some(Expr::Bool(a == b)),
+                    BinaryOp::NotEqual => Some(Expr::Bool(a != b)),
+                    BinaryOp::Less => Some(Expr::Bool(a < b)),
+                    BinaryOp::LessEqual => Some(Expr::Bool(a <= b)),
+                    BinaryOp::Greater => Some(Expr::Bool(a > b)),
+                    BinaryOp::GreaterEqual => Some(Expr::Bool(a >= b)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Operator that token stands for on given precedence level.
+    fn from_token(token: &Token, level: Level) -> Option<BinaryOp> {
+        match (level, token) {
+            (Level::Or, &Token::OrOr) => Some(BinaryOp::Or),
+            (Level::And, &Token::AndAnd) => Some(BinaryOp::And),
+            (Level::Comparison, &Token::EqualEqual) => Some(BinaryOp::Equal),
+            (Level::Comparison, &Token::BangEqual) => Some(BinaryOp::NotEqual),
+            (Level::Comparison, &Token::Less) => Some(BinaryOp::Less),
+            (Level::Comparison, &Token::LessEqual) => Some(BinaryOp::LessEqual),
+            (Level::Comparison, &Token::Greater) => Some(BinaryOp::Greater),
+            (Level::Comparison, &Token::GreaterEqual) => Some(BinaryOp::GreaterEqual),
+            (Level::Sum, &Token::Plus) => Some(BinaryOp::Add),
+            (Level::Sum, &Token::Minus) => Some(BinaryOp::Subtract),
+            (Level::Product, &Token::Star) => Some(BinaryOp::Multiply),
+            (Level::Product, &Token::Slash) => Some(BinaryOp::Divide),
+            _ => None,
+        }
+    }
+}
+
+/// Precedence levels of binary operators from the loosest binding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Level {
+    Or,
+    And,
+    Comparison,
+    Sum,
+    Product,
+}
+
+impl Level {
+    /// Level binding tighter than this one, `None` for the tightest.
+    fn next(&self) -> Option<Level> {
+        match *self {
+            Level::Or => Some(Level::And),
+            Level::And => Some(Level::Comparison),
+            Level::Comparison => Some(Level::Sum),
+            Level::Sum => Some(Level::Product),
+            Level::Product => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f32),
+    Bool(bool),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// Call of wizard function together with its arguments.
+    Call(Instruction, Vec<Expr>),
+}
+
+/// Top level expression with position where it starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub line: usize,
+    pub column: usize,
+    pub expr: Expr,
+}
+
+impl Expr {
+    /// Whether expression leaves a value on the stack.
+    fn has_value(&self) -> bool {
+        match *self {
+            Expr::Call(instruction, _) => instruction.stack_effect().1 > 0,
+            _ => true,
+        }
+    }
+
+    /// Longest path from expression down to a leaf.
+    fn height(&self) -> usize {
+        match *self {
+            Expr::Not(ref operand) => operand.height() + 1,
+            Expr::Binary(_, ref lhs, ref rhs) => cmp::max(lhs.height(), rhs.height()) + 1,
+            Expr::Call(_, ref args) => args.iter().map(Expr::height).max().unwrap_or(0) + 1,
+            _ => 0,
+        }
+    }
+}
+
+
+// ================================================================================================
+// Errors
+// ================================================================================================
+
+#[derive(Debug, PartialEq)]
+pub enum CompileErrorKind {
+    UnexpectedCharacter(char),
+    UnexpectedToken(String),
+    UnexpectedEnd,
+    UnknownFunction(String),
+    WrongArgumentCount {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    /// Call that does not return anything was used as a value.
+    NoValue(String),
+    /// Constant passed as a wizard is not a valid entity id.
+    InvalidEntity(f32),
+    /// Expression is nested deeper than `MAX_NESTING`.
+    TooDeep,
+}
+
+/// Error together with line and column (both counting from 1) where it was found.
+#[derive(Debug, PartialEq)]
+pub struct CompileError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: CompileErrorKind,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match self.kind {
+            CompileErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character `{}`", c),
+            CompileErrorKind::UnexpectedToken(ref t) => write!(f, "unexpected `{}`", t),
+            CompileErrorKind::UnexpectedEnd => write!(f, "unexpected end of input"),
+            CompileErrorKind::UnknownFunction(ref name) => write!(f, "unknown function `{}`", name),
+            CompileErrorKind::WrongArgumentCount { ref function, expected, found } => {
+                write!(f,
+                       "`{}` takes {} arguments but {} were given",
+                       function,
+                       expected,
+                       found)
+            }
+            CompileErrorKind::NoValue(ref name) => write!(f, "`{}` does not return a value", name),
+            CompileErrorKind::InvalidEntity(n) => write!(f, "{} is not a valid wizard", n),
+            CompileErrorKind::TooDeep => write!(f, "expression is nested too deep"),
+        }
+    }
+}
+
+
+// ================================================================================================
+// Lexer
+// ================================================================================================
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f32),
+    Ident(String),
+    LeftParen,
+    RightParen,
+    Comma,
+    Semicolon,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Bang,
+    EqualEqual,
+    BangEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    AndAnd,
+    OrOr,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Ident(ref name) => write!(f, "{}", name),
+            Token::LeftParen => write!(f, "("),
+            Token::RightParen => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+            Token::Semicolon => write!(f, ";"),
+            Token::Plus => write!(f, "+"),
+            Token::Minus => write!(f, "-"),
+            Token::Star => write!(f, "*"),
+            Token::Slash => write!(f, "/"),
+            Token::Bang => write!(f, "!"),
+            Token::EqualEqual => write!(f, "=="),
+            Token::BangEqual => write!(f, "!="),
+            Token::Less => write!(f, "<"),
+            Token::LessEqual => write!(f, "<="),
+            Token::Greater => write!(f, ">"),
+            Token::GreaterEqual => write!(f, ">="),
+            Token::AndAnd => write!(f, "&&"),
+            Token::OrOr => write!(f, "||"),
+        }
+    }
+}
+
+/// Token with its position in source.
+#[derive(Debug, Clone)]
+struct Spanned {
+    token: Token,
+    line: usize,
+    column: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Spanned>, CompileError> {
+    let mut tokens = Vec::new();
+    for (l, text) in source.lines().enumerate() {
+        let chars: Vec<char> = text.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let start = i;
+            let pair = match (c, chars.get(i + 1)) {
+                ('=', Some(&'=')) => Some(Token::EqualEqual),
+                ('!', Some(&'=')) => Some(Token::BangEqual),
+                ('<', Some(&'=')) => Some(Token::LessEqual),
+                ('>', Some(&'=')) => Some(Token::GreaterEqual),
+                ('&', Some(&'&')) => Some(Token::AndAnd),
+                ('|', Some(&'|')) => Some(Token::OrOr),
+                _ => None,
+            };
+            if let Some(token) = pair {
+                tokens.push(Spanned {
+                    token: token,
+                    line: l + 1,
+                    column: start + 1,
+                });
+                i += 2;
+                continue;
+            }
+            let token = match c {
+                ' ' | '\t' | '\r' => {
+                    i += 1;
+                    continue;
+                }
+                '(' => Token::LeftParen,
+                ')' => Token::RightParen,
+                ',' => Token::Comma,
+                ';' => Token::Semicolon,
+                '+' => Token::Plus,
+                '-' => Token::Minus,
+                '*' => Token::Star,
+                '/' => Token::Slash,
+                '!' => Token::Bang,
+                '<' => Token::Less,
+                '>' => Token::Greater,
+                c if c.is_digit(10) || c == '.' => {
+                    while i < chars.len() && (chars[i].is_digit(10) || chars[i] == '.') {
+                        i += 1;
+                    }
+                    let number: String = chars[start..i].iter().cloned().collect();
+                    i -= 1;
+                    match number.parse() {
+                        Ok(n) => Token::Number(n),
+                        Err(_) => {
+                            return Err(CompileError {
+                                line: l + 1,
+                                column: start + 1,
+                                kind: CompileErrorKind::UnexpectedToken(number),
+                            })
+                        }
+                    }
+                }
+                c if c.is_alphabetic() || c == '_' => {
+                    while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                        i += 1;
+                    }
+                    let name = chars[start..i].iter().cloned().collect();
+                    i -= 1;
+                    Token::Ident(name)
+                }
+                c => {
+                    return Err(CompileError {
+                        line: l + 1,
+                        column: start + 1,
+                        kind: CompileErrorKind::UnexpectedCharacter(c),
+                    })
+                }
+            };
+            tokens.push(Spanned {
+                token: token,
+                line: l + 1,
+                column: start + 1,
+            });
+            i += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+
+// ================================================================================================
+// Parser
+// ================================================================================================
+
+/// Find function by name.
+fn function(name: &str) -> Option<Instruction> {
+    match name {
+        "set_health" => Some(Instruction::SetHealth),
+        "set_agility" => Some(Instruction::SetAgility),
+        "set_wisdom" => Some(Instruction::SetWisdom),
+        "play_sound" => Some(Instruction::PlaySound),
+        "spawn_particles" => Some(Instruction::SpawnParticles),
+        "get_health" => Some(Instruction::GetHealth),
+        "get_agility" => Some(Instruction::GetAgility),
+        "get_wisdom" => Some(Instruction::GetWisdom),
+        "yield" => Some(Instruction::Yield),
+        "wait" => Some(Instruction::Wait),
+        _ => None,
+    }
+}
+
+/// Recursive descent parser. Grammar:
+///
+/// ```text
+/// program    = statement { ";" statement } [ ";" ]
+/// statement  = expr
+/// expr       = and { "||" and }
+/// and        = comparison { "&&" comparison }
+/// comparison = sum { ( "==" | "!=" | "<" | "<=" | ">" | ">=" ) sum }
+/// sum        = product { ( "+" | "-" ) product }
+/// product    = unary { ( "*" | "/" ) unary }
+/// unary      = ( "!" | "-" ) unary | factor
+/// factor     = number | call | "(" expr ")"
+/// call       = ident "(" [ expr { "," expr } ] ")"
+/// ```
+struct Parser {
+    tokens: Vec<Spanned>,
+    pos: usize,
+    /// How many nested expressions are being parsed.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|s| &s.token)
+    }
+
+    fn error(&self, kind: CompileErrorKind) -> CompileError {
+        let (line, column) = match self.tokens.get(self.pos) {
+            Some(s) => (s.line, s.column),
+            None => {
+                self.tokens.last().map(|s| (s.line, s.column + 1)).unwrap_or((1, 1))
+            }
+        };
+        CompileError {
+            line: line,
+            column: column,
+            kind: kind,
+        }
+    }
+
+    fn unexpected(&self) -> CompileError {
+        match self.peek() {
+            Some(token) => self.error(CompileErrorKind::UnexpectedToken(token.to_string())),
+            None => self.error(CompileErrorKind::UnexpectedEnd),
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), CompileError> {
+        if self.peek() == Some(&token) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    /// Parse nested expression, failing instead of recursing deeper than `MAX_NESTING`.
+    fn nested<F>(&mut self, parse: F) -> Result<Expr, CompileError>
+        where F: FnOnce(&mut Parser) -> Result<Expr, CompileError>
+    {
+        if self.depth == MAX_NESTING {
+            return Err(self.error(CompileErrorKind::TooDeep));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn program(&mut self) -> Result<Vec<Statement>, CompileError> {
+        let mut statements = Vec::new();
+        while let Some(start) = self.tokens.get(self.pos).cloned() {
+            statements.push(Statement {
+                line: start.line,
+                column: start.column,
+                expr: self.expr()?,
+            });
+            match self.peek() {
+                Some(&Token::Semicolon) => self.pos += 1,
+                Some(_) => return Err(self.unexpected()),
+                None => {}
+            }
+        }
+        Ok(statements)
+    }
+
+    fn expr(&mut self) -> Result<Expr, CompileError> {
+        self.binary_level(Level::Or)
+    }
+
+    /// Parse left associative chain of operators on given precedence level.
+    fn binary_level(&mut self, level: Level) -> Result<Expr, CompileError> {
+        let operand = |parser: &mut Parser| {
+            match level.next() {
+                Some(next) => parser.binary_level(next),
+                None => parser.unary(),
+            }
+        };
+        let mut lhs = operand(self)?;
+        while let Some(op) = self.peek().and_then(|t| BinaryOp::from_token(t, level)) {
+            self.pos += 1;
+            let rhs = operand(self)?;
+            lhs = self.binary(op, lhs, rhs)?;
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        match self.peek() {
+            Some(&Token::Bang) => {
+                self.pos += 1;
+                let operand = self.nested(Parser::unary)?;
+                Ok(Expr::Not(Box::new(self.value(operand)?)))
+            }
+            Some(&Token::Minus) => {
+                self.pos += 1;
+                let operand = self.nested(Parser::unary)?;
+                self.binary(BinaryOp::Subtract, Expr::Number(0.0), operand)
+            }
+            _ => self.factor(),
+        }
+    }
+
+    fn binary(&self, op: BinaryOp, lhs: Expr, rhs: Expr) -> Result<Expr, CompileError> {
+        let expr = Expr::Binary(op, Box::new(self.value(lhs)?), Box::new(self.value(rhs)?));
+        if expr.height() > MAX_NESTING {
+            return Err(self.error(CompileErrorKind::TooDeep));
+        }
+        Ok(expr)
+    }
+
+    /// Make sure expression can be used as a value.
+    fn value(&self, expr: Expr) -> Result<Expr, CompileError> {
+        match expr {
+            Expr::Call(instruction, _) if !expr.has_value() => {
+                let name = instruction.mnemonic().to_lowercase();
+                Err(self.error(CompileErrorKind::NoValue(name)))
+            }
+            expr => Ok(expr),
+        }
+    }
+
+    fn factor(&mut self) -> Result<Expr, CompileError> {
+        match self.peek().cloned() {
+            Some(Token::Number(n)) => {
+                self.pos += 1;
+                Ok(Expr::Number(n))
+            }
+            Some(Token::Ident(ref name)) if name == "true" || name == "false" => {
+                self.pos += 1;
+                Ok(Expr::Bool(name == "true"))
+            }
+            Some(Token::LeftParen) => {
+                self.pos += 1;
+                let expr = self.nested(Parser::expr)?;
+                self.expect(Token::RightParen)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => {
+                let instruction = function(&name)
+                    .ok_or_else(|| self.error(CompileErrorKind::UnknownFunction(name.clone())))?;
+                self.pos += 1;
+                self.call(instruction, name)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn call(&mut self, instruction: Instruction, name: String) -> Result<Expr, CompileError> {
+        self.expect(Token::LeftParen)?;
+        let mut args = Vec::new();
+        if self.peek() != Some(&Token::RightParen) {
+            loop {
+                let arg = self.nested(Parser::expr)?;
+                args.push(self.value(arg)?);
+                if self.peek() == Some(&Token::Comma) {
+                    self.pos += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+        self.expect(Token::RightParen)?;
+
+        let expected = instruction.stack_effect().0;
+        if args.len() != expected {
+            return Err(self.error(CompileErrorKind::WrongArgumentCount {
+                function: name,
+                expected: expected,
+                found: args.len(),
+            }));
+        }
+        Ok(Expr::Call(instruction, args))
+    }
+}
+
+/// Parse source into list of statements.
+pub fn parse(source: &str) -> Result<Vec<Statement>, CompileError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        pos: 0,
+        depth: 0,
+    };
+    parser.program()
+}
+
+
+// ================================================================================================
+// Code generation
+// ================================================================================================
+
+/// Replace arithmetic on constants with its result.
+pub fn fold(expr: Expr) -> Expr {
+    match expr {
+        Expr::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = (fold(*lhs), fold(*rhs));
+            op.fold(&lhs, &rhs).unwrap_or_else(|| Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+        }
+        Expr::Not(operand) => {
+            match fold(*operand) {
+                Expr::Bool(b) => Expr::Bool(!b),
+                operand => Expr::Not(Box::new(operand)),
+            }
+        }
+        Expr::Call(instruction, args) => {
+            Expr::Call(instruction, args.into_iter().map(fold).collect())
+        }
+        expr => expr,
+    }
+}
+
+fn emit(expr: &Expr, bytecode: &mut Vec<u8>) -> Result<(), CompileErrorKind> {
+    match *expr {
+        Expr::Number(n) if n.fract() == 0.0 && n >= 0.0 && n <= 255.0 => {
+            bytecode.push(Instruction::Literal as u8);
+            bytecode.push(n as u8);
+        }
+        Expr::Number(n) if n.fract() == 0.0 && n >= i32::min_value() as f32 &&
+                           n < i32::max_value() as f32 => {
+            bytecode.push(Instruction::LiteralI32 as u8);
+            write_u32(bytecode, n as i32 as u32);
+        }
+        Expr::Number(n) => {
+            bytecode.push(Instruction::LiteralF32 as u8);
+            write_u32(bytecode, n.to_bits());
+        }
+        Expr::Bool(true) => bytecode.push(Instruction::True as u8),
+        Expr::Bool(false) => bytecode.push(Instruction::False as u8),
+        Expr::Not(ref operand) => {
+            emit(operand, bytecode)?;
+            bytecode.push(Instruction::Not as u8);
+        }
+        Expr::Binary(op, ref lhs, ref rhs) => {
+            emit(lhs, bytecode)?;
+            emit(rhs, bytecode)?;
+            bytecode.push(op.instruction() as u8);
+        }
+        Expr::Call(instruction, ref args) => {
+            for (i, arg) in args.iter().enumerate() {
+                match *arg {
+                    Expr::Number(n) if i == 0 && takes_wizard(instruction) => {
+                        if n.fract() != 0.0 || n < 0.0 || n > u16::max_value() as f32 {
+                            return Err(CompileErrorKind::InvalidEntity(n));
+                        }
+                        bytecode.push(Instruction::Entity as u8);
+                        write_u16(bytecode, n as u16);
+                    }
+                    _ => emit(arg, bytecode)?,
+                }
+            }
+            bytecode.push(instruction as u8);
+        }
+    }
+    Ok(())
+}
+
+/// Whether first argument of the function is a wizard. Numbers given there are compiled into
+/// entities.
+fn takes_wizard(instruction: Instruction) -> bool {
+    match instruction {
+        Instruction::SetHealth |
+        Instruction::SetAgility |
+        Instruction::SetWisdom |
+        Instruction::GetHealth |
+        Instruction::GetAgility |
+        Instruction::GetWisdom => true,
+        _ => false,
+    }
+}
+
+/// Compile spell source into bytecode runnable by `VM`.
+pub fn compile(source: &str) -> Result<Vec<u8>, CompileError> {
+    compile_module(source).map(|module| module.code)
+}
+
+/// Compile spell into a module with a single `main` function and line of every statement.
+pub fn compile_module(source: &str) -> Result<Module, CompileError> {
+    let mut module = Module::default();
+    module.functions.push(Function {
+        name: "main".to_owned(),
+        offset: 0,
+    });
+    let statements = parse(source)?;
+    let last = statements.len().saturating_sub(1);
+    for (i, Statement { line, column, expr }) in statements.into_iter().enumerate() {
+        module.lines.push(LineEntry {
+            offset: module.code.len(),
+            line: line,
+        });
+        let dropped = i != last && expr.has_value();
+        emit(&fold(expr), &mut module.code).map_err(|kind| {
+                CompileError {
+                    line: line,
+                    column: column,
+                    kind: kind,
+                }
+            })?;
+        if dropped {
+            module.code.push(Instruction::Pop as u8);
+        }
+    }
+    Ok(module)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{compile, compile_module, CompileError, CompileErrorKind, MAX_NESTING};
+    use bytecode::VM;
+    use bytecode::host::ConsoleHost;
+    use bytecode::value::Value;
+
+    #[test]
+    fn compiler() {
+        let source = "set_health(0, get_health(0) + (get_agility(0) + get_wisdom(0)) / 2)";
+        let bytecode = compile(source).unwrap();
+        assert!(bytecode ==
+                vec![33, 0, 0, 33, 0, 0, 7, 33, 0, 0, 8, 33, 0, 0, 9, 10, 1, 2, 11, 10, 2]);
+
+        // Constant arithmetic is folded in to single literal.
+        assert!(compile("play_sound((4 + 6) / 2 + 1);").unwrap() == vec![1, 6, 5]);
+
+        let mut vm = VM::new(compile("get_health(0)").unwrap());
+        vm.interpret(&mut ConsoleHost).unwrap();
+        assert!(vm.stack == vec![Value::Number(5.0)]);
+
+        let module = compile_module("play_sound(1);\n\nplay_sound(2)").unwrap();
+        assert!(module.entry_point("main") == Some(0));
+        assert!(module.line_at(3) == Some(3));
+
+        assert!(compile("set_health(0)") ==
+                Err(CompileError {
+            line: 1,
+            column: 14,
+            kind: CompileErrorKind::WrongArgumentCount {
+                function: "set_health".to_owned(),
+                expected: 2,
+                found: 1,
+            },
+        }));
+        assert!(compile("1 + play_sound(1)").unwrap_err().kind ==
+                CompileErrorKind::NoValue("play_sound".to_owned()));
+        assert!(compile("!(2 * 3 - 6 < 1) || false").unwrap() == vec![35]);
+        assert!(compile("wait(2 * 30); yield()").unwrap() == vec![1, 60, 38, 37]);
+        assert!(compile("-get_health(0) * 2").unwrap() ==
+                vec![1, 0, 33, 0, 0, 7, 12, 1, 2, 13]);
+
+        // Numbers that don't fit in a byte use wide literals. Only the last value is kept.
+        let wide = compile("1000; -1; 1 / 2").unwrap();
+        assert!(wide ==
+                vec![30, 232, 3, 0, 0, 27, 30, 255, 255, 255, 255, 27, 31, 0, 0, 0, 63]);
+        let mut vm = VM::new(wide);
+        vm.interpret(&mut ConsoleHost).unwrap();
+        assert!(vm.stack == vec![Value::Number(0.5)]);
+        assert!(compile("get_health(0.5)").unwrap_err().kind ==
+                CompileErrorKind::InvalidEntity(0.5));
+
+        let nested = format!("{}1{}", "(".repeat(MAX_NESTING), ")".repeat(MAX_NESTING));
+        assert!(compile(&nested).unwrap() == vec![1, 1]);
+        assert!(compile(&format!("-{}", nested)).unwrap_err().kind == CompileErrorKind::TooDeep);
+        assert!(compile(&"(".repeat(100000)).unwrap_err().kind == CompileErrorKind::TooDeep);
+        assert!(compile(&"!".repeat(100000)).unwrap_err().kind == CompileErrorKind::TooDeep);
+        let chain = format!("get_health(0){}", " + get_health(0)".repeat(100000));
+        assert!(compile(&chain).unwrap_err().kind == CompileErrorKind::TooDeep);
+    }
+}
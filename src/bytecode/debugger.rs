@@ -0,0 +1,193 @@
+//! Step debugger and execution tracer.
+//!
+//! `Debugger` wraps a `VM`, stops at breakpoints set on byte offsets and hands every executed
+//! instruction to a `TraceSink`. `TraceLog` keeps them as disassembled text together with the
+//! stack, so a spell can be followed without printing anything from inside the VM.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use bytecode::{Step, VM, VmError};
+use bytecode::assembler::disassemble_instruction;
+use bytecode::host::VmHost;
+use bytecode::value::Value;
+
+
+/// Receives every instruction executed under the `Debugger`.
+pub trait TraceSink {
+    fn record(&mut self, bytecode: &[u8], step: &Step);
+}
+
+/// Ignores everything, used when no tracing is wanted.
+impl TraceSink for () {
+    fn record(&mut self, _bytecode: &[u8], _step: &Step) {}
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub offset: usize,
+    /// Instruction as disassembler shows it.
+    pub text: String,
+    /// Stack after the instruction executed, bottom first.
+    pub stack: Vec<Value>,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}  {:<24}[", self.offset, self.text)?;
+        for (i, value) in self.stack.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", value)?;
+        }
+        write!(f, "]")
+    }
+}
+
+/// Trace kept in memory.
+#[derive(Debug, Default)]
+pub struct TraceLog {
+    pub entries: Vec<TraceEntry>,
+}
+
+impl TraceLog {
+    pub fn new() -> TraceLog {
+        TraceLog::default()
+    }
+}
+
+impl TraceSink for TraceLog {
+    fn record(&mut self, bytecode: &[u8], step: &Step) {
+        self.entries.push(TraceEntry {
+            offset: step.offset,
+            text: disassemble_instruction(bytecode, step.offset),
+            stack: step.stack.clone(),
+        });
+    }
+}
+
+impl fmt::Display for TraceLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Why `Debugger::resume` returned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stop {
+    /// Next instruction to execute has a breakpoint on it.
+    Breakpoint(usize),
+    Finished,
+}
+
+pub struct Debugger<T: TraceSink = ()> {
+    pub vm: VM,
+    pub trace: T,
+    breakpoints: BTreeSet<usize>,
+}
+
+impl Debugger {
+    pub fn new(vm: VM) -> Debugger {
+        Debugger::with_trace(vm, ())
+    }
+}
+
+impl<T: TraceSink> Debugger<T> {
+    pub fn with_trace(vm: VM, trace: T) -> Debugger<T> {
+        Debugger {
+            vm: vm,
+            trace: trace,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, offset: usize) {
+        self.breakpoints.insert(offset);
+    }
+
+    /// Returns whether there was a breakpoint at offset.
+    pub fn remove_breakpoint(&mut self, offset: usize) -> bool {
+        self.breakpoints.remove(&offset)
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<usize> {
+        &self.breakpoints
+    }
+
+    /// Execute single instruction, ignoring breakpoints.
+    pub fn step<H: VmHost>(&mut self, host: &mut H) -> Result<Option<Step>, VmError> {
+        let step = self.vm.step(host)?;
+        if let Some(ref step) = step {
+            self.trace.record(&self.vm.bytecode, step);
+        }
+        Ok(step)
+    }
+
+    /// Run until the next breakpoint or the end of bytecode. Instruction at `ip` always executes,
+    /// so resuming from a breakpoint doesn't stop at it again.
+    pub fn resume<H: VmHost>(&mut self, host: &mut H) -> Result<Stop, VmError> {
+        while self.step(host)?.is_some() {
+            if self.breakpoints.contains(&self.vm.ip) && !self.vm.is_finished() {
+                return Ok(Stop::Breakpoint(self.vm.ip));
+            }
+        }
+        Ok(Stop::Finished)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Debugger, Stop, TraceLog};
+    use bytecode::{Instruction, VM};
+    use bytecode::assembler::assemble;
+    use bytecode::host::WizardRoster;
+    use bytecode::value::Value;
+
+    #[test]
+    fn debugger() {
+        let countdown = assemble("
+                    LITERAL 2
+            loop:   DUP
+                    LITERAL 0
+                    GREATER
+                    JUMP_IF_FALSE end
+                    LITERAL 1
+                    SUBTRACT
+                    JUMP loop
+            end:    POP
+        ")
+            .unwrap();
+        let mut roster = WizardRoster::default();
+
+        let mut debugger = Debugger::new(VM::new(countdown.clone()));
+        let step = debugger.step(&mut roster).unwrap().unwrap();
+        assert!(step.offset == 0);
+        assert!(step.instruction == Instruction::Literal);
+        assert!(step.stack == vec![Value::Number(2.0)]);
+
+        // Stops at the top of the loop every time around.
+        debugger.add_breakpoint(2);
+        assert!(debugger.resume(&mut roster) == Ok(Stop::Breakpoint(2)));
+        assert!(debugger.resume(&mut roster) == Ok(Stop::Breakpoint(2)));
+        assert!(debugger.vm.stack == vec![Value::Number(0.0)]);
+        assert!(debugger.remove_breakpoint(2));
+        assert!(debugger.resume(&mut roster) == Ok(Stop::Finished));
+        assert!(debugger.step(&mut roster) == Ok(None));
+
+        let mut debugger = Debugger::with_trace(VM::new(countdown), TraceLog::new());
+        debugger.resume(&mut roster).unwrap();
+        let trace = &debugger.trace.entries;
+        assert!(trace.len() == 20);
+        assert!(trace[4].text == "JUMP_IF_FALSE 15");
+        assert!(trace[4].stack == vec![Value::Number(2.0)]);
+        assert!(trace.last().unwrap().text == "POP");
+        assert!(debugger.trace.to_string().starts_with("0000  LITERAL 2               [2]\n"));
+    }
+}
@@ -0,0 +1,227 @@
+//! Bindings between the VM and the game.
+//!
+//! Instructions that touch the game world don't do anything themselves, they call into a
+//! `VmHost` given to `VM::interpret`. That way the same bytecode can drive actual wizards, a
+//! test double or just print what it would do.
+
+use std::fmt;
+
+
+/// Errors host can report back to the running script.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HostError {
+    UnknownWizard(usize),
+}
+
+impl fmt::Display for HostError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HostError::UnknownWizard(wizard) => write!(f, "there is no wizard nr {}", wizard),
+        }
+    }
+}
+
+
+/// Native functions available to bytecode.
+pub trait VmHost {
+    fn set_health(&mut self, wizard: usize, value: f32) -> Result<(), HostError>;
+    fn set_wisdom(&mut self, wizard: usize, value: f32) -> Result<(), HostError>;
+    fn set_agility(&mut self, wizard: usize, value: f32) -> Result<(), HostError>;
+    fn get_health(&mut self, wizard: usize) -> Result<f32, HostError>;
+    fn get_wisdom(&mut self, wizard: usize) -> Result<f32, HostError>;
+    fn get_agility(&mut self, wizard: usize) -> Result<f32, HostError>;
+    /// Hosts without audio can simply ignore sounds.
+    fn play_sound(&mut self, _id: usize) -> Result<(), HostError> {
+        Ok(())
+    }
+    /// Hosts without rendering can simply ignore particles.
+    fn spawn_particles(&mut self, _id: usize) -> Result<(), HostError> {
+        Ok(())
+    }
+}
+
+
+/// Host that only prints what scripts are doing. Every wizard has the same stats.
+#[derive(Debug, Default)]
+pub struct ConsoleHost;
+
+impl ConsoleHost {
+    pub fn new() -> ConsoleHost {
+        ConsoleHost
+    }
+}
+
+impl VmHost for ConsoleHost {
+    fn set_health(&mut self, wizard: usize, value: f32) -> Result<(), HostError> {
+        println!("Changing wizard nr {} health to: {}", wizard, value);
+        Ok(())
+    }
+    fn set_wisdom(&mut self, wizard: usize, value: f32) -> Result<(), HostError> {
+        println!("Changing wizard nr {} wisdom to: {}", wizard, value);
+        Ok(())
+    }
+    fn set_agility(&mut self, wizard: usize, value: f32) -> Result<(), HostError> {
+        println!("Changing wizard nr {} agility to: {}", wizard, value);
+        Ok(())
+    }
+    fn get_health(&mut self, wizard: usize) -> Result<f32, HostError> {
+        println!("Getting health of wizard nr {}", wizard);
+        Ok(5.0)
+    }
+    fn get_wisdom(&mut self, wizard: usize) -> Result<f32, HostError> {
+        println!("Getting wisdom of wizard nr {}", wizard);
+        Ok(12.0)
+    }
+    fn get_agility(&mut self, wizard: usize) -> Result<f32, HostError> {
+        println!("Getting agility of wizard nr {}", wizard);
+        Ok(8.0)
+    }
+    fn play_sound(&mut self, id: usize) -> Result<(), HostError> {
+        println!("Playing sound with id: {}", id);
+        Ok(())
+    }
+    fn spawn_particles(&mut self, id: usize) -> Result<(), HostError> {
+        println!("Spawning particle system with id: {}", id);
+        Ok(())
+    }
+}
+
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Wizard {
+    pub health: f32,
+    pub wisdom: f32,
+    pub agility: f32,
+}
+
+impl Wizard {
+    pub fn new(health: f32, wisdom: f32, agility: f32) -> Wizard {
+        Wizard {
+            health: health,
+            wisdom: wisdom,
+            agility: agility,
+        }
+    }
+}
+
+/// In memory wizards indexed by their position. Sounds and particles are stored in order they
+/// were requested.
+#[derive(Debug, Default)]
+pub struct WizardRoster {
+    pub wizards: Vec<Wizard>,
+    pub sounds: Vec<usize>,
+    pub particles: Vec<usize>,
+}
+
+impl WizardRoster {
+    pub fn new(wizards: Vec<Wizard>) -> WizardRoster {
+        WizardRoster {
+            wizards: wizards,
+            sounds: Vec::new(),
+            particles: Vec::new(),
+        }
+    }
+
+    fn wizard(&mut self, wizard: usize) -> Result<&mut Wizard, HostError> {
+        self.wizards.get_mut(wizard).ok_or(HostError::UnknownWizard(wizard))
+    }
+}
+
+impl VmHost for WizardRoster {
+    fn set_health(&mut self, wizard: usize, value: f32) -> Result<(), HostError> {
+        self.wizard(wizard)?.health = value;
+        Ok(())
+    }
+    fn set_wisdom(&mut self, wizard: usize, value: f32) -> Result<(), HostError> {
+        self.wizard(wizard)?.wisdom = value;
+        Ok(())
+    }
+    fn set_agility(&mut self, wizard: usize, value: f32) -> Result<(), HostError> {
+        self.wizard(wizard)?.agility = value;
+        Ok(())
+    }
+    fn get_health(&mut self, wizard: usize) -> Result<f32, HostError> {
+        self.wizard(wizard).map(|w| w.health)
+    }
+    fn get_wisdom(&mut self, wizard: usize) -> Result<f32, HostError> {
+        self.wizard(wizard).map(|w| w.wisdom)
+    }
+    fn get_agility(&mut self, wizard: usize) -> Result<f32, HostError> {
+        self.wizard(wizard).map(|w| w.agility)
+    }
+    fn play_sound(&mut self, id: usize) -> Result<(), HostError> {
+        self.sounds.push(id);
+        Ok(())
+    }
+    fn spawn_particles(&mut self, id: usize) -> Result<(), HostError> {
+        self.particles.push(id);
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{HostError, VmHost, Wizard, WizardRoster};
+    use bytecode::{VM, VmError};
+    use bytecode::compiler::compile;
+
+    /// Test double that remembers every call made by the VM.
+    #[derive(Default)]
+    struct Recorder {
+        calls: Vec<String>,
+    }
+
+    impl VmHost for Recorder {
+        fn set_health(&mut self, wizard: usize, value: f32) -> Result<(), HostError> {
+            self.calls.push(format!("set_health({}, {})", wizard, value));
+            Ok(())
+        }
+        fn set_wisdom(&mut self, wizard: usize, value: f32) -> Result<(), HostError> {
+            self.calls.push(format!("set_wisdom({}, {})", wizard, value));
+            Ok(())
+        }
+        fn set_agility(&mut self, wizard: usize, value: f32) -> Result<(), HostError> {
+            self.calls.push(format!("set_agility({}, {})", wizard, value));
+            Ok(())
+        }
+        fn get_health(&mut self, wizard: usize) -> Result<f32, HostError> {
+            self.calls.push(format!("get_health({})", wizard));
+            Ok(1.0)
+        }
+        fn get_wisdom(&mut self, wizard: usize) -> Result<f32, HostError> {
+            self.calls.push(format!("get_wisdom({})", wizard));
+            Ok(2.0)
+        }
+        fn get_agility(&mut self, wizard: usize) -> Result<f32, HostError> {
+            self.calls.push(format!("get_agility({})", wizard));
+            Ok(3.0)
+        }
+    }
+
+    #[test]
+    fn host() {
+        let spell = compile("set_health(1, get_health(1) + (get_agility(0) + get_wisdom(0)) / 2); \
+                             play_sound(3)")
+            .unwrap();
+
+        let mut roster = WizardRoster::new(vec![Wizard::new(10.0, 12.0, 8.0),
+                                                Wizard::new(5.0, 0.0, 0.0)]);
+        VM::new(spell.clone()).interpret(&mut roster).unwrap();
+        assert!(roster.wizards[1].health == 15.0);
+        assert!(roster.wizards[0].health == 10.0);
+        assert!(roster.sounds == vec![3]);
+
+        let mut recorder = Recorder::default();
+        VM::new(spell).interpret(&mut recorder).unwrap();
+        assert!(recorder.calls ==
+                vec!["get_health(1)", "get_agility(0)", "get_wisdom(0)", "set_health(1, 3.5)"]);
+
+        let mut vm = VM::new(compile("get_health(2)").unwrap());
+        assert!(vm.interpret(&mut roster) ==
+                Err(VmError::Host {
+            offset: 3,
+            error: HostError::UnknownWizard(2),
+        }));
+    }
+}
@@ -0,0 +1,923 @@
+//! Bytecode Pattern
+//! http://gameprogrammingpatterns.com/bytecode.html
+
+pub mod assembler;
+pub mod compiler;
+pub mod debugger;
+pub mod host;
+pub mod module;
+pub mod scheduler;
+pub mod value;
+pub mod verifier;
+
+use std::fmt;
+
+use num::FromPrimitive;
+
+use self::host::{HostError, VmHost};
+use self::value::{Value, ValueType};
+
+enum_from_primitive!{
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Instruction {
+        Literal        = 1,
+        SetHealth      = 2,
+        SetAgility     = 3,
+        SetWisdom      = 4,
+        PlaySound      = 5,
+        SpawnParticles = 6,
+        GetHealth      = 7,
+        GetAgility     = 8,
+        GetWisdom      = 9,
+        Add            = 10,
+        Divide         = 11,
+        Subtract       = 12,
+        Multiply       = 13,
+        Equal          = 14,
+        NotEqual       = 15,
+        Less           = 16,
+        LessEqual      = 17,
+        Greater        = 18,
+        GreaterEqual   = 19,
+        Not            = 20,
+        And            = 21,
+        Or             = 22,
+        Jump           = 23,
+        JumpIfFalse    = 24,
+        Dup            = 25,
+        Swap           = 26,
+        Pop            = 27,
+        GetLocal       = 28,
+        SetLocal       = 29,
+        LiteralI32     = 30,
+        LiteralF32     = 31,
+        Constant       = 32,
+        Entity         = 33,
+        True           = 34,
+        False          = 35,
+        Halt           = 36,
+        Yield          = 37,
+        Wait           = 38,
+    }
+}
+
+impl Instruction {
+    /// Name of the instruction used in assembly text.
+    pub fn mnemonic(&self) -> &'static str {
+        match *self {
+            Instruction::Literal => "LITERAL",
+            Instruction::SetHealth => "SET_HEALTH",
+            Instruction::SetAgility => "SET_AGILITY",
+            Instruction::SetWisdom => "SET_WISDOM",
+            Instruction::PlaySound => "PLAY_SOUND",
+            Instruction::SpawnParticles => "SPAWN_PARTICLES",
+            Instruction::GetHealth => "GET_HEALTH",
+            Instruction::GetAgility => "GET_AGILITY",
+            Instruction::GetWisdom => "GET_WISDOM",
+            Instruction::Add => "ADD",
+            Instruction::Divide => "DIVIDE",
+            Instruction::Subtract => "SUBTRACT",
+            Instruction::Multiply => "MULTIPLY",
+            Instruction::Equal => "EQUAL",
+            Instruction::NotEqual => "NOT_EQUAL",
+            Instruction::Less => "LESS",
+            Instruction::LessEqual => "LESS_EQUAL",
+            Instruction::Greater => "GREATER",
+            Instruction::GreaterEqual => "GREATER_EQUAL",
+            Instruction::Not => "NOT",
+            Instruction::And => "AND",
+            Instruction::Or => "OR",
+            Instruction::Jump => "JUMP",
+            Instruction::JumpIfFalse => "JUMP_IF_FALSE",
+            Instruction::Dup => "DUP",
+            Instruction::Swap => "SWAP",
+            Instruction::Pop => "POP",
+            Instruction::GetLocal => "GET_LOCAL",
+            Instruction::SetLocal => "SET_LOCAL",
+            Instruction::LiteralI32 => "LITERAL_I32",
+            Instruction::LiteralF32 => "LITERAL_F32",
+            Instruction::Constant => "CONSTANT",
+            Instruction::Entity => "ENTITY",
+            Instruction::True => "TRUE",
+            Instruction::False => "FALSE",
+            Instruction::Halt => "HALT",
+            Instruction::Yield => "YIELD",
+            Instruction::Wait => "WAIT",
+        }
+    }
+
+    /// Find instruction by its assembly mnemonic. Mnemonics are case insensitive.
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Instruction> {
+        match &*mnemonic.to_uppercase() {
+            "LITERAL" => Some(Instruction::Literal),
+            "SET_HEALTH" => Some(Instruction::SetHealth),
+            "SET_AGILITY" => Some(Instruction::SetAgility),
+            "SET_WISDOM" => Some(Instruction::SetWisdom),
+            "PLAY_SOUND" => Some(Instruction::PlaySound),
+            "SPAWN_PARTICLES" => Some(Instruction::SpawnParticles),
+            "GET_HEALTH" => Some(Instruction::GetHealth),
+            "GET_AGILITY" => Some(Instruction::GetAgility),
+            "GET_WISDOM" => Some(Instruction::GetWisdom),
+            "ADD" => Some(Instruction::Add),
+            "DIVIDE" => Some(Instruction::Divide),
+            "SUBTRACT" => Some(Instruction::Subtract),
+            "MULTIPLY" => Some(Instruction::Multiply),
+            "EQUAL" => Some(Instruction::Equal),
+            "NOT_EQUAL" => Some(Instruction::NotEqual),
+            "LESS" => Some(Instruction::Less),
+            "LESS_EQUAL" => Some(Instruction::LessEqual),
+            "GREATER" => Some(Instruction::Greater),
+            "GREATER_EQUAL" => Some(Instruction::GreaterEqual),
+            "NOT" => Some(Instruction::Not),
+            "AND" => Some(Instruction::And),
+            "OR" => Some(Instruction::Or),
+            "JUMP" => Some(Instruction::Jump),
+            "JUMP_IF_FALSE" => Some(Instruction::JumpIfFalse),
+            "DUP" => Some(Instruction::Dup),
+            "SWAP" => Some(Instruction::Swap),
+            "POP" => Some(Instruction::Pop),
+            "GET_LOCAL" => Some(Instruction::GetLocal),
+            "SET_LOCAL" => Some(Instruction::SetLocal),
+            "LITERAL_I32" => Some(Instruction::LiteralI32),
+            "LITERAL_F32" => Some(Instruction::LiteralF32),
+            "CONSTANT" => Some(Instruction::Constant),
+            "ENTITY" => Some(Instruction::Entity),
+            "TRUE" => Some(Instruction::True),
+            "FALSE" => Some(Instruction::False),
+            "HALT" => Some(Instruction::Halt),
+            "YIELD" => Some(Instruction::Yield),
+            "WAIT" => Some(Instruction::Wait),
+            _ => None,
+        }
+    }
+
+    /// Number of bytes following the opcode that belong to this instruction. Multi byte
+    /// operands are little endian. Jump targets are absolute byte offsets.
+    pub fn operand_len(&self) -> usize {
+        match *self {
+            Instruction::Literal |
+            Instruction::GetLocal |
+            Instruction::SetLocal => 1,
+            Instruction::Jump |
+            Instruction::JumpIfFalse |
+            Instruction::Constant |
+            Instruction::Entity => 2,
+            Instruction::LiteralI32 |
+            Instruction::LiteralF32 => 4,
+            _ => 0,
+        }
+    }
+
+    /// Whether operand of this instruction is a jump target.
+    pub fn is_jump(&self) -> bool {
+        *self == Instruction::Jump || *self == Instruction::JumpIfFalse
+    }
+
+    /// How many values instruction pops from the stack and how many it pushes back.
+    pub fn stack_effect(&self) -> (usize, usize) {
+        match *self {
+            Instruction::Literal |
+            Instruction::LiteralI32 |
+            Instruction::LiteralF32 |
+            Instruction::Constant |
+            Instruction::Entity |
+            Instruction::True |
+            Instruction::False => (0, 1),
+            Instruction::SetHealth |
+            Instruction::SetAgility |
+            Instruction::SetWisdom => (2, 0),
+            Instruction::PlaySound |
+            Instruction::SpawnParticles => (1, 0),
+            Instruction::GetHealth |
+            Instruction::GetAgility |
+            Instruction::GetWisdom => (1, 1),
+            Instruction::Add |
+            Instruction::Divide |
+            Instruction::Subtract |
+            Instruction::Multiply |
+            Instruction::Equal |
+            Instruction::NotEqual |
+            Instruction::Less |
+            Instruction::LessEqual |
+            Instruction::Greater |
+            Instruction::GreaterEqual |
+            Instruction::And |
+            Instruction::Or => (2, 1),
+            Instruction::Not => (1, 1),
+            Instruction::Jump |
+            Instruction::Halt |
+            Instruction::Yield => (0, 0),
+            Instruction::JumpIfFalse |
+            Instruction::Wait |
+            Instruction::Pop |
+            Instruction::SetLocal => (1, 0),
+            Instruction::Dup => (1, 2),
+            Instruction::Swap => (2, 2),
+            Instruction::GetLocal => (0, 1),
+        }
+    }
+}
+
+/// Default limit of values that can be on the stack at once.
+pub const STACK_LIMIT: usize = 128;
+
+/// Number of local variable slots available to a script.
+pub const LOCAL_SLOTS: usize = 16;
+
+/// What happens when script divides by zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DivideByZero {
+    /// Stop the script with `VmError::DivisionByZero`.
+    Error,
+    /// Result of the division is zero.
+    Zero,
+    /// Follow IEEE 754 and produce infinity or NaN.
+    Ieee,
+}
+
+/// Reasons for which VM stops executing bytecode. Offsets are byte offsets of the instruction
+/// that failed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VmError {
+    StackUnderflow { offset: usize },
+    StackOverflow { offset: usize, limit: usize },
+    InvalidOpcode { offset: usize, byte: u8 },
+    /// Bytecode ended before all operand bytes of the instruction.
+    TruncatedOperand {
+        offset: usize,
+        instruction: Instruction,
+    },
+    DivisionByZero { offset: usize },
+    /// Value used as sound or particle id or as frame count is not a whole non negative number.
+    InvalidIndex { offset: usize, value: f32 },
+    TypeMismatch {
+        offset: usize,
+        expected: ValueType,
+        found: ValueType,
+    },
+    InvalidConstant { offset: usize, index: usize },
+    Host { offset: usize, error: HostError },
+    /// Jump to an offset outside of the bytecode. Jumps into the middle of an instruction are
+    /// reported only by the verifier, `VM` decodes the bytes found there as instructions.
+    InvalidJump { offset: usize, target: usize },
+    InvalidLocal { offset: usize, slot: usize },
+    /// Paths joining at the offset leave different amount of values on the stack. Reported only
+    /// by the verifier.
+    InconsistentStack {
+        offset: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VmError::StackUnderflow { offset } => write!(f, "{:04}: stack underflow", offset),
+            VmError::StackOverflow { offset, limit } => {
+                write!(f, "{:04}: stack overflow, limit is {}", offset, limit)
+            }
+            VmError::InvalidOpcode { offset, byte } => {
+                write!(f, "{:04}: invalid opcode {}", offset, byte)
+            }
+            VmError::TruncatedOperand { offset, instruction } => {
+                write!(f, "{:04}: operand of {} is missing", offset, instruction.mnemonic())
+            }
+            VmError::DivisionByZero { offset } => write!(f, "{:04}: division by zero", offset),
+            VmError::InvalidIndex { offset, value } => {
+                write!(f, "{:04}: {} is not a valid index", offset, value)
+            }
+            VmError::TypeMismatch { offset, expected, found } => {
+                write!(f, "{:04}: expected {} but found {}", offset, expected, found)
+            }
+            VmError::InvalidConstant { offset, index } => {
+                write!(f, "{:04}: there is no constant {}", offset, index)
+            }
+            VmError::Host { offset, error } => write!(f, "{:04}: {}", offset, error),
+            VmError::InvalidJump { offset, target } => {
+                write!(f, "{:04}: invalid jump target {}", offset, target)
+            }
+            VmError::InvalidLocal { offset, slot } => {
+                write!(f, "{:04}: there is no local slot {}", offset, slot)
+            }
+            VmError::InconsistentStack { offset, expected, found } => {
+                write!(f,
+                       "{:04}: stack depth is {} on one path and {} on another",
+                       offset,
+                       expected,
+                       found)
+            }
+        }
+    }
+}
+
+/// Outcome of running the VM for a limited amount of instructions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Finished,
+    /// Budget ran out before bytecode finished. Calling `VM::run` again continues from where it
+    /// stopped.
+    Suspended,
+    /// Script executed `Yield` or `Wait` or is still waiting. It continues on a later call to
+    /// `VM::run`.
+    Yielded,
+}
+
+/// Instruction executed by `VM::step`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub offset: usize,
+    pub instruction: Instruction,
+    /// Stack after the instruction executed, bottom first.
+    pub stack: Vec<Value>,
+}
+
+
+#[derive(Debug)]
+pub struct VM {
+    pub bytecode: Vec<u8>,
+    /// Values pushed with `Instruction::Constant`.
+    pub constants: Vec<Value>,
+    /// Text of strings referred to by `Value::Str`.
+    pub strings: Vec<String>,
+    pub stack: Vec<Value>,
+    pub locals: [Value; LOCAL_SLOTS],
+    /// Offset of the next instruction to execute.
+    pub ip: usize,
+    /// Offset execution starts from and `reset` rewinds to.
+    pub entry: usize,
+    /// Number of calls to `run` left to skip because of `Wait`.
+    pub wait: usize,
+    /// Maximum number of values on the stack.
+    pub stack_limit: usize,
+    pub divide_by_zero: DivideByZero,
+}
+
+impl VM {
+    pub fn new(bytecode: Vec<u8>) -> VM {
+        VM {
+            stack: Vec::with_capacity(STACK_LIMIT),
+            locals: [Value::default(); LOCAL_SLOTS],
+            ip: 0,
+            entry: 0,
+            wait: 0,
+            bytecode: bytecode,
+            constants: Vec::new(),
+            strings: Vec::new(),
+            stack_limit: STACK_LIMIT,
+            divide_by_zero: DivideByZero::Error,
+        }
+    }
+
+    /// Create VM with constant pool and string table for the bytecode.
+    pub fn with_constants(bytecode: Vec<u8>, constants: Vec<Value>, strings: Vec<String>) -> VM {
+        let mut vm = VM::new(bytecode);
+        vm.constants = constants;
+        vm.strings = strings;
+        vm
+    }
+
+    /// Rewind to the entry point and clear stack and locals.
+    pub fn reset(&mut self) {
+        self.ip = self.entry;
+        self.wait = 0;
+        self.stack.clear();
+        self.locals = [Value::default(); LOCAL_SLOTS];
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.ip >= self.bytecode.len()
+    }
+
+    fn push(&mut self, offset: usize, val: Value) -> Result<(), VmError> {
+        if self.stack.len() >= self.stack_limit {
+            return Err(VmError::StackOverflow {
+                offset: offset,
+                limit: self.stack_limit,
+            });
+        }
+        self.stack.push(val);
+        Ok(())
+    }
+
+    fn pop(&mut self, offset: usize) -> Result<Value, VmError> {
+        self.stack.pop().ok_or(VmError::StackUnderflow { offset: offset })
+    }
+
+    fn pop_number(&mut self, offset: usize) -> Result<f32, VmError> {
+        match self.pop(offset)? {
+            Value::Number(n) => Ok(n),
+            value => Err(mismatch(offset, ValueType::Number, value)),
+        }
+    }
+
+    fn pop_bool(&mut self, offset: usize) -> Result<bool, VmError> {
+        match self.pop(offset)? {
+            Value::Bool(b) => Ok(b),
+            value => Err(mismatch(offset, ValueType::Bool, value)),
+        }
+    }
+
+    fn pop_entity(&mut self, offset: usize) -> Result<usize, VmError> {
+        match self.pop(offset)? {
+            Value::Entity(id) => Ok(id),
+            value => Err(mismatch(offset, ValueType::Entity, value)),
+        }
+    }
+
+    /// Pop number that is used to identify sound or particle system.
+    fn pop_index(&mut self, offset: usize) -> Result<usize, VmError> {
+        let value = self.pop_number(offset)?;
+        if value >= 0.0 && value.fract() == 0.0 && value <= usize::max_value() as f32 {
+            Ok(value as usize)
+        } else {
+            Err(VmError::InvalidIndex {
+                offset: offset,
+                value: value,
+            })
+        }
+    }
+
+    /// Local slot named by operand of instruction at given offset.
+    fn local_slot(&self, offset: usize) -> Result<usize, VmError> {
+        let slot = self.bytecode[offset + 1] as usize;
+        if slot < LOCAL_SLOTS {
+            Ok(slot)
+        } else {
+            Err(VmError::InvalidLocal {
+                offset: offset,
+                slot: slot,
+            })
+        }
+    }
+
+    /// Jump target of instruction at given offset.
+    fn jump_target(&self, offset: usize) -> Result<usize, VmError> {
+        let target = read_u16(&self.bytecode, offset + 1) as usize;
+        if target <= self.bytecode.len() {
+            Ok(target)
+        } else {
+            Err(VmError::InvalidJump {
+                offset: offset,
+                target: target,
+            })
+        }
+    }
+
+    /// Run the rest of bytecode without waiting. Execution stops on first error, no bytecode can
+    /// make it panic.
+    pub fn interpret<H: VmHost>(&mut self, host: &mut H) -> Result<(), VmError> {
+        while self.run(host, usize::max_value())? != Status::Finished {}
+        Ok(())
+    }
+
+    /// Execute at most `budget` instructions, so long running scripts can be spread over many
+    /// frames. Meant to be called once per frame, `Yield` ends execution for the current frame
+    /// and `Wait` for as many frames as it pops. On error `ip` is left pointing at the failed
+    /// instruction.
+    pub fn run<H: VmHost>(&mut self, host: &mut H, budget: usize) -> Result<Status, VmError> {
+        if self.wait > 0 {
+            self.wait -= 1;
+            return Ok(Status::Yielded);
+        }
+        for _ in 0..budget {
+            if self.is_finished() {
+                break;
+            }
+            match self.execute_next(host)? {
+                (_, Instruction::Yield) |
+                (_, Instruction::Wait) => return Ok(Status::Yielded),
+                _ => {}
+            }
+        }
+        if self.is_finished() {
+            Ok(Status::Finished)
+        } else {
+            Ok(Status::Suspended)
+        }
+    }
+
+    /// Execute single instruction and take a snapshot of the stack. Returns `None` when bytecode
+    /// is already finished. Doesn't skip frames left to wait.
+    pub fn step<H: VmHost>(&mut self, host: &mut H) -> Result<Option<Step>, VmError> {
+        if self.is_finished() {
+            return Ok(None);
+        }
+        let (offset, instruction) = self.execute_next(host)?;
+        Ok(Some(Step {
+            offset: offset,
+            instruction: instruction,
+            stack: self.stack.clone(),
+        }))
+    }
+
+    /// Execute instruction at `ip` and return its offset.
+    fn execute_next<H: VmHost>(&mut self, host: &mut H) -> Result<(usize, Instruction), VmError> {
+        let offset = self.ip;
+        let instruction = decode(&self.bytecode, offset)?;
+        self.ip += 1 + instruction.operand_len();
+        if let Err(error) = self.interpret_instruction(host, offset, instruction) {
+            self.ip = offset;
+            return Err(error);
+        }
+        Ok((offset, instruction))
+    }
+
+    /// Interpret instruction at given offset and call associated host function.
+    fn interpret_instruction<H: VmHost>(&mut self,
+                                        host: &mut H,
+                                        offset: usize,
+                                        instruction: Instruction)
+                                        -> Result<(), VmError> {
+        let host_error = |error| {
+            VmError::Host {
+                offset: offset,
+                error: error,
+            }
+        };
+        match instruction {
+            Instruction::SetHealth => {
+                let amount = self.pop_number(offset)?;
+                let wizard = self.pop_entity(offset)?;
+                host.set_health(wizard, amount).map_err(host_error)?;
+            }
+            Instruction::SetAgility => {
+                let amount = self.pop_number(offset)?;
+                let wizard = self.pop_entity(offset)?;
+                host.set_agility(wizard, amount).map_err(host_error)?;
+            }
+            Instruction::SetWisdom => {
+                let amount = self.pop_number(offset)?;
+                let wizard = self.pop_entity(offset)?;
+                host.set_wisdom(wizard, amount).map_err(host_error)?;
+            }
+            Instruction::PlaySound => {
+                let id = self.pop_index(offset)?;
+                host.play_sound(id).map_err(host_error)?;
+            }
+            Instruction::SpawnParticles => {
+                let id = self.pop_index(offset)?;
+                host.spawn_particles(id).map_err(host_error)?;
+            }
+            Instruction::GetHealth => {
+                let wizard = self.pop_entity(offset)?;
+                let health = host.get_health(wizard).map_err(host_error)?;
+                self.push(offset, Value::Number(health))?;
+            }
+            Instruction::GetAgility => {
+                let wizard = self.pop_entity(offset)?;
+                let agility = host.get_agility(wizard).map_err(host_error)?;
+                self.push(offset, Value::Number(agility))?;
+            }
+            Instruction::GetWisdom => {
+                let wizard = self.pop_entity(offset)?;
+                let wisdom = host.get_wisdom(wizard).map_err(host_error)?;
+                self.push(offset, Value::Number(wisdom))?;
+            }
+            Instruction::Add => {
+                let b = self.pop_number(offset)?;
+                let a = self.pop_number(offset)?;
+                self.push(offset, Value::Number(a + b))?;
+            }
+            Instruction::Subtract => {
+                let b = self.pop_number(offset)?;
+                let a = self.pop_number(offset)?;
+                self.push(offset, Value::Number(a - b))?;
+            }
+            Instruction::Multiply => {
+                let b = self.pop_number(offset)?;
+                let a = self.pop_number(offset)?;
+                self.push(offset, Value::Number(a * b))?;
+            }
+            Instruction::Divide => {
+                let b = self.pop_number(offset)?;
+                let a = self.pop_number(offset)?;
+                let result = match self.divide_by_zero {
+                    DivideByZero::Error if b == 0.0 => {
+                        return Err(VmError::DivisionByZero { offset: offset })
+                    }
+                    DivideByZero::Zero if b == 0.0 => 0.0,
+                    _ => a / b,
+                };
+                self.push(offset, Value::Number(result))?;
+            }
+            Instruction::Literal => {
+                let value = self.bytecode[offset + 1];
+                self.push(offset, Value::Number(value as f32))?;
+            }
+            Instruction::LiteralI32 => {
+                let value = read_u32(&self.bytecode, offset + 1) as i32;
+                self.push(offset, Value::Number(value as f32))?;
+            }
+            Instruction::LiteralF32 => {
+                let value = f32::from_bits(read_u32(&self.bytecode, offset + 1));
+                self.push(offset, Value::Number(value))?;
+            }
+            Instruction::Constant => {
+                let index = read_u16(&self.bytecode, offset + 1) as usize;
+                let value = *self.constants.get(index).ok_or(VmError::InvalidConstant {
+                        offset: offset,
+                        index: index,
+                    })?;
+                self.push(offset, value)?;
+            }
+            Instruction::Entity => {
+                let id = read_u16(&self.bytecode, offset + 1) as usize;
+                self.push(offset, Value::Entity(id))?;
+            }
+            Instruction::True => self.push(offset, Value::Bool(true))?,
+            Instruction::False => self.push(offset, Value::Bool(false))?,
+            Instruction::Equal |
+            Instruction::NotEqual => {
+                let b = self.pop(offset)?;
+                let a = self.pop(offset)?;
+                if a.value_type() != b.value_type() {
+                    return Err(mismatch(offset, a.value_type(), b));
+                }
+                let equal = a == b;
+                self.push(offset, Value::Bool(equal == (instruction == Instruction::Equal)))?;
+            }
+            Instruction::Less |
+            Instruction::LessEqual |
+            Instruction::Greater |
+            Instruction::GreaterEqual => {
+                let b = self.pop_number(offset)?;
+                let a = self.pop_number(offset)?;
+                let result = match instruction {
+                    Instruction::Less => a < b,
+                    Instruction::LessEqual => a <= b,
+                    Instruction::Greater => a > b,
+                    _ => a >= b,
+                };
+                self.push(offset, Value::Bool(result))?;
+            }
+            Instruction::Not => {
+                let value = self.pop_bool(offset)?;
+                self.push(offset, Value::Bool(!value))?;
+            }
+            Instruction::And => {
+                let b = self.pop_bool(offset)?;
+                let a = self.pop_bool(offset)?;
+                self.push(offset, Value::Bool(a && b))?;
+            }
+            Instruction::Or => {
+                let b = self.pop_bool(offset)?;
+                let a = self.pop_bool(offset)?;
+                self.push(offset, Value::Bool(a || b))?;
+            }
+            Instruction::Jump => {
+                self.ip = self.jump_target(offset)?;
+            }
+            Instruction::Halt => {
+                self.ip = self.bytecode.len();
+            }
+            Instruction::Yield => {}
+            Instruction::Wait => {
+                // Resume on the n-th next frame, so waiting a single frame is the same as yield.
+                self.wait = self.pop_index(offset)?.saturating_sub(1);
+            }
+            Instruction::JumpIfFalse => {
+                let target = self.jump_target(offset)?;
+                if !self.pop_bool(offset)? {
+                    self.ip = target;
+                }
+            }
+            Instruction::Dup => {
+                let value = self.pop(offset)?;
+                self.push(offset, value)?;
+                self.push(offset, value)?;
+            }
+            Instruction::Swap => {
+                let b = self.pop(offset)?;
+                let a = self.pop(offset)?;
+                self.push(offset, b)?;
+                self.push(offset, a)?;
+            }
+            Instruction::Pop => {
+                self.pop(offset)?;
+            }
+            Instruction::GetLocal => {
+                let value = self.locals[self.local_slot(offset)?];
+                self.push(offset, value)?;
+            }
+            Instruction::SetLocal => {
+                let slot = self.local_slot(offset)?;
+                self.locals[slot] = self.pop(offset)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for VM {
+    fn default() -> VM {
+        VM::new(Vec::new())
+    }
+}
+
+fn mismatch(offset: usize, expected: ValueType, found: Value) -> VmError {
+    VmError::TypeMismatch {
+        offset: offset,
+        expected: expected,
+        found: found.value_type(),
+    }
+}
+
+/// Read little endian `u16` operand.
+pub fn read_u16(bytecode: &[u8], offset: usize) -> u16 {
+    bytecode[offset] as u16 | (bytecode[offset + 1] as u16) << 8
+}
+
+/// Read little endian `u32` operand.
+pub fn read_u32(bytecode: &[u8], offset: usize) -> u32 {
+    read_u16(bytecode, offset) as u32 | (read_u16(bytecode, offset + 2) as u32) << 16
+}
+
+/// Append little endian `u16` operand.
+pub fn write_u16(bytecode: &mut Vec<u8>, value: u16) {
+    bytecode.push(value as u8);
+    bytecode.push((value >> 8) as u8);
+}
+
+/// Append little endian `u32` operand.
+pub fn write_u32(bytecode: &mut Vec<u8>, value: u32) {
+    write_u16(bytecode, value as u16);
+    write_u16(bytecode, (value >> 16) as u16);
+}
+
+/// Read instruction starting at offset, making sure all of its operand bytes are there.
+pub fn decode(bytecode: &[u8], offset: usize) -> Result<Instruction, VmError> {
+    let byte = bytecode[offset];
+    let instruction = Instruction::from_u8(byte).ok_or(VmError::InvalidOpcode {
+            offset: offset,
+            byte: byte,
+        })?;
+    if offset + instruction.operand_len() >= bytecode.len() {
+        return Err(VmError::TruncatedOperand {
+            offset: offset,
+            instruction: instruction,
+        });
+    }
+    Ok(instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::assembler::assemble;
+    use super::host::{ConsoleHost, WizardRoster};
+
+    #[test]
+    fn bytecode() {
+        let mut bytecode: Vec<u8> = Vec::new();
+        // set_health(0, get_health(0) + (get_agility(0) + get_wisdom(0)) / 2);
+        bytecode.push(33); // Entity                        []
+        bytecode.push(0);  // Wizard index
+        bytecode.push(0);  //                               [0]
+        bytecode.push(33); // Entity
+        bytecode.push(0);  // Wizard index
+        bytecode.push(0);  //                               [0, 0]
+        bytecode.push(7);  // Get health                    [0, 5]
+        bytecode.push(33); // Entity
+        bytecode.push(0);  // Wizard index
+        bytecode.push(0);  //                               [0, 5, 0]
+        bytecode.push(8);  // Get Agility                   [0, 5, 8]
+        bytecode.push(33); // Entity
+        bytecode.push(0);  // Wizard index
+        bytecode.push(0);  //                               [0, 5, 8, 0]
+        bytecode.push(9);  // Get Wisdom                    [0, 5, 8, 12]
+        bytecode.push(10); // Add Agility and wisdom        [0, 5, 20]
+        bytecode.push(1);  // Literal
+        bytecode.push(2);  // Divisor                       [0, 5, 20, 2]
+        bytecode.push(11); // Divide                        [0, 5, 10]
+        bytecode.push(10); // Add average to current health [0, 15]
+        bytecode.push(2);  // Set Health                    []
+        let mut vm = VM::new(bytecode);
+        vm.interpret(&mut ConsoleHost).unwrap();
+
+        bytecode = Vec::new();
+        bytecode.push(33); // Entity
+        bytecode.push(0);  // Index
+        bytecode.push(0);
+        bytecode.push(7);  // GetHealth
+        vm = VM::new(bytecode);
+        vm.interpret(&mut ConsoleHost).unwrap();
+        assert!(vm.stack[0] == Value::Number(5.0), "Should be get_health value");
+    }
+
+    #[test]
+    fn errors() {
+        let host = &mut ConsoleHost;
+        assert!(VM::new(vec![10]).interpret(host) == Err(VmError::StackUnderflow { offset: 0 }));
+        assert!(VM::new(vec![1, 0, 255]).interpret(host) ==
+                Err(VmError::InvalidOpcode {
+            offset: 2,
+            byte: 255,
+        }));
+        assert!(VM::new(vec![1, 0, 1]).interpret(host) ==
+                Err(VmError::TruncatedOperand {
+            offset: 2,
+            instruction: Instruction::Literal,
+        }));
+
+        let mut vm = VM::new(vec![1, 0, 1, 0, 1, 0]);
+        vm.stack_limit = 2;
+        assert!(vm.interpret(host) ==
+                Err(VmError::StackOverflow {
+            offset: 4,
+            limit: 2,
+        }));
+
+        let divide = vec![1, 4, 1, 0, 11];
+        vm = VM::new(divide.clone());
+        assert!(vm.interpret(host) == Err(VmError::DivisionByZero { offset: 4 }));
+        vm = VM::new(divide);
+        vm.divide_by_zero = DivideByZero::Zero;
+        vm.interpret(host).unwrap();
+        assert!(vm.stack == vec![Value::Number(0.0)]);
+    }
+
+    #[test]
+    fn control_flow() {
+        let countdown = assemble("
+                    LITERAL 3
+                    SET_LOCAL 0
+            loop:   GET_LOCAL 0
+                    LITERAL 0
+                    GREATER
+                    JUMP_IF_FALSE end
+                    GET_LOCAL 0
+                    PLAY_SOUND
+                    GET_LOCAL 0
+                    LITERAL 1
+                    SUBTRACT
+                    SET_LOCAL 0
+                    JUMP loop
+            end:
+        ")
+            .unwrap();
+
+        let mut roster = WizardRoster::default();
+        VM::new(countdown.clone()).interpret(&mut roster).unwrap();
+        assert!(roster.sounds == vec![3, 2, 1]);
+
+        // Same script spread over several frames.
+        roster = WizardRoster::default();
+        let mut vm = VM::new(countdown);
+        let mut frames = 1;
+        while vm.run(&mut roster, 10).unwrap() == Status::Suspended {
+            frames += 1;
+        }
+        assert!(frames == 4);
+        assert!(roster.sounds == vec![3, 2, 1]);
+        assert!(vm.stack.is_empty());
+
+        vm = VM::new(vec![1, 2, 1, 3, 26, 12, 25, 13]); // (3 - 2)^2
+        vm.interpret(&mut roster).unwrap();
+        assert!(vm.stack == vec![Value::Number(1.0)]);
+    }
+
+    #[test]
+    fn values() {
+        let program = assemble("
+            LITERAL_I32 -300
+            LITERAL_F32 1.5
+            CONSTANT 0
+            CONSTANT 1
+            TRUE
+        ")
+            .unwrap();
+        let mut vm = VM::with_constants(program,
+                                        vec![Value::Str(0), Value::Entity(7)],
+                                        vec!["fireball".to_owned()]);
+        vm.interpret(&mut ConsoleHost).unwrap();
+        assert!(vm.stack ==
+                vec![Value::Number(-300.0),
+                     Value::Number(1.5),
+                     Value::Str(0),
+                     Value::Entity(7),
+                     Value::Bool(true)]);
+
+        // Wizards can't be picked with plain numbers any more.
+        vm = VM::new(vec![1, 0, 7]);
+        assert!(vm.interpret(&mut ConsoleHost) ==
+                Err(VmError::TypeMismatch {
+            offset: 2,
+            expected: ValueType::Entity,
+            found: ValueType::Number,
+        }));
+        vm = VM::new(vec![34, 1, 1, 10]);
+        assert!(vm.interpret(&mut ConsoleHost) ==
+                Err(VmError::TypeMismatch {
+            offset: 3,
+            expected: ValueType::Number,
+            found: ValueType::Bool,
+        }));
+        vm = VM::new(vec![32, 3, 0]);
+        assert!(vm.interpret(&mut ConsoleHost) ==
+                Err(VmError::InvalidConstant {
+            offset: 0,
+            index: 3,
+        }));
+    }
+}
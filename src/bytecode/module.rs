@@ -0,0 +1,378 @@
+//! Binary container for compiled bytecode.
+//!
+//! Module bundles bytecode with everything needed to run and debug it, so compiled spells can be
+//! cached on disk. All numbers are little endian.
+//!
+//! ```text
+//! magic       4 bytes "GPPB"
+//! version     u16
+//! length      u32 number of bytes from here up to checksum
+//! constants   u32 count, each a type tag u8 followed by the value
+//! strings     u32 count, each u32 length and UTF-8 bytes
+//! functions   u32 count, each a string name and u32 offset
+//! lines       u32 count, each u32 offset and u32 line
+//! code        u32 length and the bytecode
+//! checksum    u32 Adler-32 of everything above
+//! ```
+
+use std::fmt;
+use std::io;
+
+use bytecode::{read_u16, read_u32, write_u16, write_u32, VM};
+use bytecode::value::Value;
+
+pub const MAGIC: [u8; 4] = [b'G', b'P', b'P', b'B'];
+/// Version written by this code and the only one it can read.
+pub const VERSION: u16 = 1;
+
+const TAG_NUMBER: u8 = 0;
+const TAG_BOOL: u8 = 1;
+const TAG_STR: u8 = 2;
+const TAG_ENTITY: u8 = 3;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModuleError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    /// Data ended before the whole module was read.
+    Truncated,
+    ChecksumMismatch { expected: u32, found: u32 },
+    InvalidConstantTag(u8),
+    InvalidUtf8,
+    /// There is more data after the checksum.
+    TrailingBytes,
+    /// Function starts outside of the bytecode.
+    InvalidFunction(String),
+    UnknownEntry(String),
+    Io(io::ErrorKind),
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ModuleError::BadMagic => write!(f, "not a bytecode module"),
+            ModuleError::UnsupportedVersion(v) => {
+                write!(f, "module version {} is not supported, expected {}", v, VERSION)
+            }
+            ModuleError::Truncated => write!(f, "module is truncated"),
+            ModuleError::ChecksumMismatch { expected, found } => {
+                write!(f, "checksum is {:08x} but data sums to {:08x}", expected, found)
+            }
+            ModuleError::InvalidConstantTag(tag) => write!(f, "unknown constant type {}", tag),
+            ModuleError::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            ModuleError::TrailingBytes => write!(f, "unexpected data after checksum"),
+            ModuleError::InvalidFunction(ref name) => {
+                write!(f, "function `{}` starts outside of the code", name)
+            }
+            ModuleError::UnknownEntry(ref name) => write!(f, "there is no function `{}`", name),
+            ModuleError::Io(kind) => write!(f, "io error: {:?}", kind),
+        }
+    }
+}
+
+
+/// Named entry point into the bytecode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub offset: usize,
+}
+
+/// Source line that instructions starting at `offset` came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineEntry {
+    pub offset: usize,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Module {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    pub strings: Vec<String>,
+    pub functions: Vec<Function>,
+    /// Sorted by offset.
+    pub lines: Vec<LineEntry>,
+}
+
+impl Module {
+    pub fn new(code: Vec<u8>) -> Module {
+        Module { code: code, ..Module::default() }
+    }
+
+    /// Offset of the function with given name.
+    pub fn entry_point(&self, name: &str) -> Option<usize> {
+        self.functions.iter().find(|f| f.name == name).map(|f| f.offset)
+    }
+
+    /// Source line of the instruction at offset.
+    pub fn line_at(&self, offset: usize) -> Option<usize> {
+        self.lines.iter().take_while(|l| l.offset <= offset).last().map(|l| l.line)
+    }
+
+    /// Create VM that starts executing at the named function.
+    pub fn instantiate(&self, entry: &str) -> Result<VM, ModuleError> {
+        let offset = self.entry_point(entry)
+            .ok_or_else(|| ModuleError::UnknownEntry(entry.to_owned()))?;
+        let mut vm = VM::with_constants(self.code.clone(),
+                                        self.constants.clone(),
+                                        self.strings.clone());
+        vm.entry = offset;
+        vm.ip = offset;
+        Ok(vm)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_u32(&mut out, self.constants.len() as u32);
+        for constant in &self.constants {
+            match *constant {
+                Value::Number(n) => {
+                    out.push(TAG_NUMBER);
+                    write_u32(&mut out, n.to_bits());
+                }
+                Value::Bool(b) => {
+                    out.push(TAG_BOOL);
+                    out.push(b as u8);
+                }
+                Value::Str(id) => {
+                    out.push(TAG_STR);
+                    write_u16(&mut out, id);
+                }
+                Value::Entity(id) => {
+                    out.push(TAG_ENTITY);
+                    write_u32(&mut out, id as u32);
+                }
+            }
+        }
+
+        write_u32(&mut out, self.strings.len() as u32);
+        for string in &self.strings {
+            put_str(&mut out, string);
+        }
+
+        write_u32(&mut out, self.functions.len() as u32);
+        for function in &self.functions {
+            put_str(&mut out, &function.name);
+            write_u32(&mut out, function.offset as u32);
+        }
+
+        write_u32(&mut out, self.lines.len() as u32);
+        for entry in &self.lines {
+            write_u32(&mut out, entry.offset as u32);
+            write_u32(&mut out, entry.line as u32);
+        }
+
+        write_u32(&mut out, self.code.len() as u32);
+        out.extend_from_slice(&self.code);
+
+        let mut bytes = MAGIC.to_vec();
+        write_u16(&mut bytes, VERSION);
+        write_u32(&mut bytes, out.len() as u32);
+        bytes.extend_from_slice(&out);
+        let checksum = adler32(&bytes);
+        write_u32(&mut bytes, checksum);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Module, ModuleError> {
+        let mut reader = Reader {
+            bytes: bytes,
+            pos: 0,
+        };
+        if reader.take(4)? != MAGIC {
+            return Err(ModuleError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(ModuleError::UnsupportedVersion(version));
+        }
+        // Length is known before anything else is read, so cut data is reported as such rather
+        // than as a checksum mismatch.
+        let len = reader.u32()? as usize;
+        let end = reader.pos + len;
+        if bytes.len() < end + 4 {
+            return Err(ModuleError::Truncated);
+        }
+        if bytes.len() > end + 4 {
+            return Err(ModuleError::TrailingBytes);
+        }
+        let (data, checksum) = bytes.split_at(end);
+        let expected = read_u32(checksum, 0);
+        let found = adler32(data);
+        if expected != found {
+            return Err(ModuleError::ChecksumMismatch {
+                expected: expected,
+                found: found,
+            });
+        }
+        reader.bytes = data;
+
+        let mut module = Module::default();
+        for _ in 0..reader.u32()? {
+            let constant = match reader.u8()? {
+                TAG_NUMBER => Value::Number(f32::from_bits(reader.u32()?)),
+                TAG_BOOL => Value::Bool(reader.u8()? != 0),
+                TAG_STR => Value::Str(reader.u16()?),
+                TAG_ENTITY => Value::Entity(reader.u32()? as usize),
+                tag => return Err(ModuleError::InvalidConstantTag(tag)),
+            };
+            module.constants.push(constant);
+        }
+        for _ in 0..reader.u32()? {
+            module.strings.push(reader.string()?);
+        }
+        for _ in 0..reader.u32()? {
+            let name = reader.string()?;
+            let offset = reader.u32()? as usize;
+            module.functions.push(Function {
+                name: name,
+                offset: offset,
+            });
+        }
+        for _ in 0..reader.u32()? {
+            let offset = reader.u32()? as usize;
+            let line = reader.u32()? as usize;
+            module.lines.push(LineEntry {
+                offset: offset,
+                line: line,
+            });
+        }
+        let len = reader.u32()? as usize;
+        module.code = reader.take(len)?.to_vec();
+        if reader.pos != data.len() {
+            return Err(ModuleError::TrailingBytes);
+        }
+
+        if let Some(f) = module.functions.iter().find(|f| f.offset > module.code.len()) {
+            return Err(ModuleError::InvalidFunction(f.name.clone()));
+        }
+        Ok(module)
+    }
+
+    pub fn write_to<W: io::Write>(&self, writer: &mut W) -> Result<(), ModuleError> {
+        writer.write_all(&self.to_bytes()).map_err(|e| ModuleError::Io(e.kind()))
+    }
+
+    pub fn read_from<R: io::Read>(reader: &mut R) -> Result<Module, ModuleError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).map_err(|e| ModuleError::Io(e.kind()))?;
+        Module::from_bytes(&bytes)
+    }
+}
+
+
+fn put_str(out: &mut Vec<u8>, string: &str) {
+    write_u32(out, string.len() as u32);
+    out.extend_from_slice(string.as_bytes());
+}
+
+/// Adler-32 checksum, same as used by zlib.
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % MOD;
+        b = (b + a) % MOD;
+    }
+    b << 16 | a
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ModuleError> {
+        if self.bytes.len() - self.pos < len {
+            return Err(ModuleError::Truncated);
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ModuleError> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ModuleError> {
+        self.take(2).map(|b| read_u16(b, 0))
+    }
+
+    fn u32(&mut self) -> Result<u32, ModuleError> {
+        self.take(4).map(|b| read_u32(b, 0))
+    }
+
+    fn string(&mut self) -> Result<String, ModuleError> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| ModuleError::InvalidUtf8)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Module, ModuleError, VERSION};
+    use bytecode::assembler::assemble_module;
+    use bytecode::host::{Wizard, WizardRoster};
+    use bytecode::value::Value;
+
+    #[test]
+    fn module() {
+        let mut module = assemble_module("
+            .entry heal
+                    ENTITY 0
+                    LITERAL 20
+                    SET_HEALTH
+                    HALT
+            .entry hurt
+                    ENTITY 0
+                    CONSTANT 0
+                    SET_HEALTH
+        ")
+            .unwrap();
+        module.constants.push(Value::Number(0.5));
+        assert!(module.entry_point("hurt") == Some(7));
+        assert!(module.line_at(5) == Some(5));
+
+        let bytes = module.to_bytes();
+        let loaded = Module::from_bytes(&bytes).unwrap();
+        assert!(loaded == module);
+
+        let mut roster = WizardRoster::new(vec![Wizard::default()]);
+        loaded.instantiate("heal").unwrap().interpret(&mut roster).unwrap();
+        assert!(roster.wizards[0].health == 20.0);
+        loaded.instantiate("hurt").unwrap().interpret(&mut roster).unwrap();
+        assert!(roster.wizards[0].health == 0.5);
+        assert!(loaded.instantiate("explode").unwrap_err() ==
+                ModuleError::UnknownEntry("explode".to_owned()));
+
+        assert!(Module::from_bytes(&bytes[..bytes.len() - 10]) == Err(ModuleError::Truncated));
+        assert!(Module::from_bytes(&bytes[..bytes.len() - 1]) == Err(ModuleError::Truncated));
+        assert!(Module::from_bytes(&bytes[..8]) == Err(ModuleError::Truncated));
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Module::from_bytes(&trailing) == Err(ModuleError::TrailingBytes));
+        assert!(Module::from_bytes(&bytes[..5]) == Err(ModuleError::Truncated));
+        assert!(Module::from_bytes(b"ELF\x7f\x01\x00") == Err(ModuleError::BadMagic));
+
+        let mut stale = bytes.clone();
+        stale[4] = VERSION as u8 + 1;
+        assert!(Module::from_bytes(&stale) == Err(ModuleError::UnsupportedVersion(VERSION + 1)));
+
+        let mut corrupt = bytes.clone();
+        corrupt[20] ^= 0xff;
+        match Module::from_bytes(&corrupt) {
+            Err(ModuleError::ChecksumMismatch { .. }) => {}
+            other => panic!("expected checksum mismatch, got {:?}", other),
+        }
+
+        let mut file = Vec::new();
+        module.write_to(&mut file).unwrap();
+        assert!(Module::read_from(&mut &file[..]).unwrap() == module);
+    }
+}
@@ -0,0 +1,135 @@
+//! Runs many scripts side by side.
+//!
+//! Game loop calls `Scheduler::tick` once per frame. Every script gets to run until it yields,
+//! waits, finishes or uses up its instruction budget, and then the next one goes. Scripts that
+//! finished or failed are removed and reported back.
+
+use bytecode::{Status, VM, VmError};
+use bytecode::host::VmHost;
+
+/// Default number of instructions a script can execute in a single tick.
+pub const SCRIPT_BUDGET: usize = 1000;
+
+/// Handle to a script owned by the `Scheduler`.
+pub type ScriptId = usize;
+
+
+/// Scripts that stopped during a tick.
+#[derive(Debug, Default, PartialEq)]
+pub struct Tick {
+    pub finished: Vec<ScriptId>,
+    pub failed: Vec<(ScriptId, VmError)>,
+}
+
+#[derive(Debug)]
+pub struct Scheduler {
+    /// Kept in the order they were spawned, which is also the order they run in.
+    scripts: Vec<(ScriptId, VM)>,
+    next_id: ScriptId,
+    /// Maximum number of instructions each script can execute in a single tick.
+    pub budget: usize,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {
+            scripts: Vec::new(),
+            next_id: 0,
+            budget: SCRIPT_BUDGET,
+        }
+    }
+
+    pub fn spawn(&mut self, vm: VM) -> ScriptId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.scripts.push((id, vm));
+        id
+    }
+
+    /// Stop script before it finishes, returning its VM.
+    pub fn kill(&mut self, id: ScriptId) -> Option<VM> {
+        let index = self.scripts.iter().position(|&(script, _)| script == id);
+        index.map(|i| self.scripts.remove(i).1)
+    }
+
+    pub fn get(&self, id: ScriptId) -> Option<&VM> {
+        self.scripts.iter().find(|&&(script, _)| script == id).map(|&(_, ref vm)| vm)
+    }
+
+    /// Number of scripts still running.
+    pub fn len(&self) -> usize {
+        self.scripts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+
+    /// Run every script for one frame.
+    pub fn tick<H: VmHost>(&mut self, host: &mut H) -> Tick {
+        let mut tick = Tick::default();
+        let mut i = 0;
+        while i < self.scripts.len() {
+            match self.scripts[i].1.run(host, self.budget) {
+                Ok(Status::Finished) => tick.finished.push(self.scripts.remove(i).0),
+                Ok(_) => i += 1,
+                Err(error) => tick.failed.push((self.scripts.remove(i).0, error)),
+            }
+        }
+        tick
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Scheduler {
+        Scheduler::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Scheduler, Tick};
+    use bytecode::{VM, VmError};
+    use bytecode::assembler::assemble;
+    use bytecode::compiler::compile;
+    use bytecode::host::{HostError, Wizard, WizardRoster};
+
+    #[test]
+    fn scheduler() {
+        let mut roster = WizardRoster::new(vec![Wizard::default()]);
+        let mut scheduler = Scheduler::new();
+        let spell = compile("play_sound(1); wait(2); play_sound(2); yield(); play_sound(3)");
+        let spell = scheduler.spawn(VM::new(spell.unwrap()));
+        let broken = scheduler.spawn(VM::new(compile("wait(1); get_health(5)").unwrap()));
+        let endless = scheduler.spawn(VM::new(assemble("loop: JUMP loop").unwrap()));
+        scheduler.budget = 100;
+
+        assert!(scheduler.tick(&mut roster) == Tick::default());
+        assert!(roster.sounds == vec![1]);
+        assert!(scheduler.tick(&mut roster) ==
+                Tick {
+            finished: vec![],
+            failed: vec![(broken,
+                          VmError::Host {
+                offset: 6,
+                error: HostError::UnknownWizard(5),
+            })],
+        });
+        assert!(roster.sounds == vec![1]);
+        assert!(scheduler.tick(&mut roster) == Tick::default());
+        assert!(roster.sounds == vec![1, 2]);
+        assert!(scheduler.tick(&mut roster) ==
+                Tick {
+            finished: vec![spell],
+            failed: vec![],
+        });
+        assert!(roster.sounds == vec![1, 2, 3]);
+
+        // Endless loop only burns its budget every frame.
+        assert!(scheduler.len() == 1);
+        assert!(scheduler.get(endless).is_some());
+        assert!(scheduler.kill(endless).is_some());
+        assert!(scheduler.is_empty());
+    }
+}
@@ -0,0 +1,67 @@
+//! Values living on the VM stack.
+
+use std::fmt;
+
+use component::EntityId;
+
+/// Index of a string in `VM::strings`.
+pub type StringId = u16;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Number(f32),
+    Bool(bool),
+    Str(StringId),
+    Entity(EntityId),
+}
+
+impl Value {
+    pub fn value_type(&self) -> ValueType {
+        match *self {
+            Value::Number(_) => ValueType::Number,
+            Value::Bool(_) => ValueType::Bool,
+            Value::Str(_) => ValueType::Str,
+            Value::Entity(_) => ValueType::Entity,
+        }
+    }
+}
+
+impl Default for Value {
+    fn default() -> Value {
+        Value::Number(0.0)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(id) => write!(f, "string #{}", id),
+            Value::Entity(id) => write!(f, "entity #{}", id),
+        }
+    }
+}
+
+
+/// Type of a `Value`, used to report mismatches.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueType {
+    Number,
+    Bool,
+    Str,
+    Entity,
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            ValueType::Number => "number",
+            ValueType::Bool => "bool",
+            ValueType::Str => "string",
+            ValueType::Entity => "entity",
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,174 @@
+//! Static verification of bytecode.
+//!
+//! Walks bytecode without running it and checks everything that can be known up front, so
+//! untrusted scripts can be rejected before they ever reach the `VM`. Every path through the
+//! bytecode is followed, so all of them have to agree on stack depth wherever they meet.
+
+use std::collections::HashSet;
+
+use bytecode::{decode, read_u16, Instruction, LOCAL_SLOTS, VmError};
+
+
+/// Stack usage of a verified bytecode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StackSummary {
+    /// Highest number of values on the stack at any point.
+    pub max_depth: usize,
+    /// Number of values left on the stack once bytecode finishes. `None` when the end can never
+    /// be reached, for example because of an endless loop.
+    pub final_depth: Option<usize>,
+}
+
+/// Check that bytecode consists only of valid instructions, that all jumps land on instructions
+/// and that stack never underflows nor grows beyond `stack_limit`. Reports the same errors
+/// `VM::interpret` would, except for those that depend on actual values such as division by
+/// zero.
+pub fn verify(bytecode: &[u8], stack_limit: usize) -> Result<StackSummary, VmError> {
+    let mut starts = HashSet::new();
+    let mut offset = 0;
+    while offset < bytecode.len() {
+        let instruction = decode(bytecode, offset)?;
+        starts.insert(offset);
+        offset += 1 + instruction.operand_len();
+    }
+    starts.insert(bytecode.len());
+
+    // Depth of the stack before each offset, filled in as paths reach it.
+    let mut depths = vec![None; bytecode.len() + 1];
+    let mut pending = vec![(0, 0)];
+    let mut max_depth = 0;
+    while let Some((offset, depth)) = pending.pop() {
+        match depths[offset] {
+            Some(expected) if expected != depth => {
+                return Err(VmError::InconsistentStack {
+                    offset: offset,
+                    expected: expected,
+                    found: depth,
+                })
+            }
+            Some(_) => continue,
+            None => depths[offset] = Some(depth),
+        }
+        if offset == bytecode.len() {
+            continue;
+        }
+
+        let instruction = decode(bytecode, offset)?;
+        let (pops, pushes) = instruction.stack_effect();
+        if pops > depth {
+            return Err(VmError::StackUnderflow { offset: offset });
+        }
+        let depth = depth - pops + pushes;
+        if depth > stack_limit {
+            return Err(VmError::StackOverflow {
+                offset: offset,
+                limit: stack_limit,
+            });
+        }
+        if depth > max_depth {
+            max_depth = depth;
+        }
+
+        if instruction == Instruction::GetLocal || instruction == Instruction::SetLocal {
+            let slot = bytecode[offset + 1] as usize;
+            if slot >= LOCAL_SLOTS {
+                return Err(VmError::InvalidLocal {
+                    offset: offset,
+                    slot: slot,
+                });
+            }
+        }
+        if instruction.is_jump() {
+            let target = read_u16(bytecode, offset + 1) as usize;
+            if !starts.contains(&target) {
+                return Err(VmError::InvalidJump {
+                    offset: offset,
+                    target: target,
+                });
+            }
+            pending.push((target, depth));
+        }
+        match instruction {
+            Instruction::Jump => {}
+            Instruction::Halt => pending.push((bytecode.len(), depth)),
+            _ => pending.push((offset + 1 + instruction.operand_len(), depth)),
+        }
+    }
+    Ok(StackSummary {
+        max_depth: max_depth,
+        final_depth: depths[bytecode.len()],
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{verify, StackSummary};
+    use bytecode::{STACK_LIMIT, VM, VmError};
+    use bytecode::assembler::assemble;
+    use bytecode::host::{Wizard, WizardRoster};
+
+    #[test]
+    fn verifier() {
+        // set_health(0, get_health(0) + (get_agility(0) + get_wisdom(0)) / 2)
+        let spell = vec![33, 0, 0, 33, 0, 0, 7, 33, 0, 0, 8, 33, 0, 0, 9, 10, 1, 2, 11, 10, 2];
+        assert!(verify(&spell, STACK_LIMIT) ==
+                Ok(StackSummary {
+            max_depth: 4,
+            final_depth: Some(0),
+        }));
+        assert!(verify(&spell, 3) ==
+                Err(VmError::StackOverflow {
+            offset: 11,
+            limit: 3,
+        }));
+        VM::new(spell).interpret(&mut WizardRoster::new(vec![Wizard::default()])).unwrap();
+        assert!(verify(&[1, 0, 2], STACK_LIMIT) == Err(VmError::StackUnderflow { offset: 2 }));
+        assert!(verify(&[0], STACK_LIMIT) ==
+                Err(VmError::InvalidOpcode {
+            offset: 0,
+            byte: 0,
+        }));
+    }
+
+    #[test]
+    fn branches() {
+        let countdown = assemble("
+                    LITERAL 3
+            loop:   DUP
+                    LITERAL 0
+                    GREATER
+                    JUMP_IF_FALSE end
+                    LITERAL 1
+                    SUBTRACT
+                    JUMP loop
+            end:    POP
+        ")
+            .unwrap();
+        assert!(verify(&countdown, STACK_LIMIT) ==
+                Ok(StackSummary {
+            max_depth: 3,
+            final_depth: Some(0),
+        }));
+        VM::new(countdown).interpret(&mut WizardRoster::default()).unwrap();
+
+        // Every time around the loop leaves one more value on the stack.
+        let growing = assemble("loop: LITERAL 1\nJUMP loop").unwrap();
+        assert!(verify(&growing, STACK_LIMIT) ==
+                Err(VmError::InconsistentStack {
+            offset: 0,
+            expected: 0,
+            found: 1,
+        }));
+
+        // Jump into the operand of LITERAL.
+        assert!(verify(&[1, 0, 23, 1, 0], STACK_LIMIT) ==
+                Err(VmError::InvalidJump {
+            offset: 2,
+            target: 1,
+        }));
+
+        let forever = assemble("loop: JUMP loop").unwrap();
+        assert!(verify(&forever, STACK_LIMIT).unwrap().final_depth == None);
+    }
+}
@@ -0,0 +1,451 @@
+//! Actors and the standard actions on them.
+//!
+//! Commands here work on anything implementing `Actor` rather than on a concrete type, so the
+//! same actions serve the player, monsters and whatever else gets controlled. Each action
+//! remembers what it changed when executed and undoes exactly that, even when it turned out to
+//! do nothing, like firing without ammo.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+
+use command::Command;
+
+
+/// Something with a position on the grid.
+pub trait Movable {
+    fn position(&self) -> (i32, i32);
+    fn set_position(&mut self, x: i32, y: i32);
+}
+
+pub trait Actor: Movable {
+    fn health(&self) -> u32;
+    fn set_health(&mut self, health: u32);
+    fn facing(&self) -> Facing;
+    fn set_facing(&mut self, facing: Facing);
+    fn inventory(&self) -> &Inventory;
+    fn inventory_mut(&mut self) -> &mut Inventory;
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Facing {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Facing {
+    /// Step one tile forward, north being positive `y`.
+    pub fn offset(&self) -> (i32, i32) {
+        match *self {
+            Facing::North => (0, 1),
+            Facing::East => (1, 0),
+            Facing::South => (0, -1),
+            Facing::West => (-1, 0),
+        }
+    }
+}
+
+
+/// Items by name with how many of each there are.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Inventory {
+    items: BTreeMap<String, u32>,
+}
+
+impl Inventory {
+    pub fn new() -> Inventory {
+        Inventory::default()
+    }
+
+    pub fn add(&mut self, item: &str, count: u32) {
+        if count > 0 {
+            *self.items.entry(item.to_owned()).or_insert(0) += count;
+        }
+    }
+
+    /// Take `count` items out. Returns false and leaves inventory alone if there isn't enough.
+    pub fn remove(&mut self, item: &str, count: u32) -> bool {
+        let left = match self.items.get(item) {
+            Some(&have) if have >= count => have - count,
+            _ => return count == 0,
+        };
+        if left == 0 {
+            self.items.remove(item);
+        } else {
+            self.items.insert(item.to_owned(), left);
+        }
+        true
+    }
+
+    pub fn count(&self, item: &str) -> u32 {
+        self.items.get(item).cloned().unwrap_or(0)
+    }
+}
+
+
+/// Basic actor to control.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Character {
+    pub x: i32,
+    pub y: i32,
+    pub health: u32,
+    pub facing: Facing,
+    pub inventory: Inventory,
+}
+
+impl Character {
+    pub fn new(x: i32, y: i32, health: u32) -> Character {
+        Character {
+            x: x,
+            y: y,
+            health: health,
+            facing: Facing::North,
+            inventory: Inventory::new(),
+        }
+    }
+}
+
+impl Movable for Character {
+    fn position(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+    fn set_position(&mut self, x: i32, y: i32) {
+        self.x = x;
+        self.y = y;
+    }
+}
+
+impl Actor for Character {
+    fn health(&self) -> u32 {
+        self.health
+    }
+    fn set_health(&mut self, health: u32) {
+        self.health = health;
+    }
+    fn facing(&self) -> Facing {
+        self.facing
+    }
+    fn set_facing(&mut self, facing: Facing) {
+        self.facing = facing;
+    }
+    fn inventory(&self) -> &Inventory {
+        &self.inventory
+    }
+    fn inventory_mut(&mut self) -> &mut Inventory {
+        &mut self.inventory
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turn {
+    pub facing: Facing,
+    previous: Option<Facing>,
+}
+
+impl Turn {
+    pub fn new(facing: Facing) -> Turn {
+        Turn {
+            facing: facing,
+            previous: None,
+        }
+    }
+}
+
+impl<A: Actor + 'static> Command<A> for Turn {
+    fn execute(&mut self, actor: &mut A) {
+        self.previous = Some(actor.facing());
+        actor.set_facing(self.facing);
+    }
+    fn undo(&mut self, actor: &mut A) {
+        if let Some(facing) = self.previous.take() {
+            actor.set_facing(facing);
+        }
+    }
+    fn name(&self) -> &str {
+        "turn"
+    }
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+
+/// Leap `distance` tiles the way actor is facing, stopping at the edge of the grid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Jump {
+    pub distance: i32,
+    previous: Option<(i32, i32)>,
+}
+
+impl Jump {
+    pub fn new(distance: i32) -> Jump {
+        Jump {
+            distance: distance,
+            previous: None,
+        }
+    }
+}
+
+impl<A: Actor + 'static> Command<A> for Jump {
+    fn execute(&mut self, actor: &mut A) {
+        let (x, y) = actor.position();
+        let (dx, dy) = actor.facing().offset();
+        self.previous = Some((x, y));
+        let leap = |from: i32, step: i32| from.saturating_add(step.saturating_mul(self.distance));
+        actor.set_position(leap(x, dx), leap(y, dy));
+    }
+    fn undo(&mut self, actor: &mut A) {
+        if let Some((x, y)) = self.previous.take() {
+            actor.set_position(x, y);
+        }
+    }
+    fn name(&self) -> &str {
+        "jump"
+    }
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+
+/// Use up one `ammo` item. Nothing happens without it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fire {
+    pub ammo: String,
+    fired: bool,
+}
+
+impl Fire {
+    pub fn new(ammo: &str) -> Fire {
+        Fire {
+            ammo: ammo.to_owned(),
+            fired: false,
+        }
+    }
+}
+
+impl<A: Actor + 'static> Command<A> for Fire {
+    fn execute(&mut self, actor: &mut A) {
+        self.fired = actor.inventory_mut().remove(&self.ammo, 1);
+    }
+    fn undo(&mut self, actor: &mut A) {
+        if self.fired {
+            actor.inventory_mut().add(&self.ammo, 1);
+            self.fired = false;
+        }
+    }
+    fn name(&self) -> &str {
+        "fire"
+    }
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PickUp {
+    pub item: String,
+    pub count: u32,
+}
+
+impl PickUp {
+    pub fn new(item: &str, count: u32) -> PickUp {
+        PickUp {
+            item: item.to_owned(),
+            count: count,
+        }
+    }
+}
+
+impl<A: Actor + 'static> Command<A> for PickUp {
+    fn execute(&mut self, actor: &mut A) {
+        actor.inventory_mut().add(&self.item, self.count);
+    }
+    fn undo(&mut self, actor: &mut A) {
+        actor.inventory_mut().remove(&self.item, self.count);
+    }
+    fn name(&self) -> &str {
+        "pick up"
+    }
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+
+/// Drop `count` items, or nothing if actor doesn't have that many.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DropItem {
+    pub item: String,
+    pub count: u32,
+    dropped: bool,
+}
+
+impl DropItem {
+    pub fn new(item: &str, count: u32) -> DropItem {
+        DropItem {
+            item: item.to_owned(),
+            count: count,
+            dropped: false,
+        }
+    }
+}
+
+impl<A: Actor + 'static> Command<A> for DropItem {
+    fn execute(&mut self, actor: &mut A) {
+        self.dropped = actor.inventory_mut().remove(&self.item, self.count);
+    }
+    fn undo(&mut self, actor: &mut A) {
+        if self.dropped {
+            actor.inventory_mut().add(&self.item, self.count);
+            self.dropped = false;
+        }
+    }
+    fn name(&self) -> &str {
+        "drop"
+    }
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+
+/// One actor hitting another. Works on all actors as it involves two of them, which are given
+/// by their index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attack {
+    pub attacker: usize,
+    pub target: usize,
+    pub damage: u32,
+    /// Target's health and attacker's facing.
+    previous: Option<(u32, Facing)>,
+}
+
+impl Attack {
+    pub fn new(attacker: usize, target: usize, damage: u32) -> Attack {
+        Attack {
+            attacker: attacker,
+            target: target,
+            damage: damage,
+            previous: None,
+        }
+    }
+}
+
+impl<A: Actor + 'static> Command<Vec<A>> for Attack {
+    /// Attacker turns to face the target if it's in a straight line from it. Nothing happens if
+    /// either of them isn't there.
+    fn execute(&mut self, actors: &mut Vec<A>) {
+        self.previous = None;
+        let ((x, y), facing) = match actors.get(self.attacker) {
+            Some(attacker) => (attacker.position(), attacker.facing()),
+            None => return,
+        };
+        let ((tx, ty), health) = match actors.get(self.target) {
+            Some(target) => (target.position(), target.health()),
+            None => return,
+        };
+        let turn = match (tx - x, ty - y) {
+            (0, dy) if dy > 0 => Some(Facing::North),
+            (0, dy) if dy < 0 => Some(Facing::South),
+            (dx, 0) if dx > 0 => Some(Facing::East),
+            (dx, 0) if dx < 0 => Some(Facing::West),
+            _ => None,
+        };
+        self.previous = Some((health, facing));
+        if let Some(target) = actors.get_mut(self.target) {
+            target.set_health(health.saturating_sub(self.damage));
+        }
+        if let (Some(attacker), Some(turn)) = (actors.get_mut(self.attacker), turn) {
+            attacker.set_facing(turn);
+        }
+    }
+    fn undo(&mut self, actors: &mut Vec<A>) {
+        if let Some((health, facing)) = self.previous.take() {
+            if let Some(target) = actors.get_mut(self.target) {
+                target.set_health(health);
+            }
+            if let Some(attacker) = actors.get_mut(self.attacker) {
+                attacker.set_facing(facing);
+            }
+        }
+    }
+    fn name(&self) -> &str {
+        "attack"
+    }
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Attack, Character, DropItem, Facing, Fire, Jump, PickUp, Turn};
+    use command::{Command, MoveUnit};
+    use command::history::CommandHistory;
+
+    #[test]
+    fn actions() {
+        let mut hero = Character::new(0, 0, 10);
+        let mut history = CommandHistory::new(10);
+        history.execute(&mut hero, Box::new(Turn::new(Facing::East)));
+        history.execute(&mut hero, Box::new(Jump::new(2)));
+        history.execute(&mut hero, Box::new(MoveUnit::new(2, 5)));
+        assert!(hero.x == 2 && hero.y == 5 && hero.facing == Facing::East);
+        history.undo(&mut hero);
+        assert!(hero.x == 2 && hero.y == 0);
+        history.undo(&mut hero);
+        history.undo(&mut hero);
+        assert!(hero == Character::new(0, 0, 10));
+
+        history.execute(&mut hero, Box::new(Jump::new(i32::max_value())));
+        history.execute(&mut hero, Box::new(Jump::new(i32::max_value())));
+        assert!(hero.x == 0 && hero.y == i32::max_value());
+        history.undo(&mut hero);
+        history.undo(&mut hero);
+        assert!(hero == Character::new(0, 0, 10));
+
+        history.execute(&mut hero, Box::new(PickUp::new("arrow", 2)));
+        for _ in 0..3 {
+            history.execute(&mut hero, Box::new(Fire::new("arrow")));
+        }
+        assert!(hero.inventory.count("arrow") == 0);
+        // Undoing shot that didn't happen gives nothing back.
+        history.undo(&mut hero);
+        assert!(hero.inventory.count("arrow") == 0);
+        history.undo(&mut hero);
+        assert!(hero.inventory.count("arrow") == 1);
+
+        let mut drop = DropItem::new("arrow", 2);
+        Command::<Character>::execute(&mut drop, &mut hero);
+        assert!(hero.inventory.count("arrow") == 1);
+        Command::<Character>::undo(&mut drop, &mut hero);
+        assert!(hero.inventory.count("arrow") == 1);
+    }
+
+    #[test]
+    fn attack() {
+        let mut actors = vec![Character::new(0, 0, 10), Character::new(0, -3, 5)];
+        let mut history = CommandHistory::new(10);
+        history.execute(&mut actors, Box::new(Attack::new(0, 1, 3)));
+        history.execute(&mut actors, Box::new(Attack::new(0, 1, 3)));
+        assert!(actors[1].health == 0);
+        assert!(actors[0].facing == Facing::South);
+        history.undo(&mut actors);
+        assert!(actors[1].health == 2);
+        history.undo(&mut actors);
+        assert!(actors[1].health == 5);
+        assert!(actors[0].facing == Facing::North);
+
+        // Attacking someone who's gone does nothing, and so does undoing it.
+        history.execute(&mut actors, Box::new(Attack::new(0, 5, 3)));
+        history.execute(&mut actors, Box::new(Attack::new(5, 1, 3)));
+        assert!(history.undo(&mut actors) && history.undo(&mut actors));
+        assert!(actors[1].health == 5 && actors[0].facing == Facing::North);
+    }
+}
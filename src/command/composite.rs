@@ -0,0 +1,221 @@
+//! Commands made of other commands.
+//!
+//! `MacroCommand` runs a group of commands as one, so an editor operation such as moving a
+//! selection of units is undone in a single step. `Indexed` points a command at one element of
+//! a `Vec`, which is how a selection is addressed. When the group is only known as the user goes
+//! along, e.g. during a drag, `MacroBuilder` executes commands right away and hands over the
+//! finished macro once the operation ends.
+
+use std::any::Any;
+
+use command::{Command, Unit};
+
+
+/// Commands executed in order and undone in reverse.
+pub struct MacroCommand<T = Unit> {
+    name: String,
+    commands: Vec<Box<Command<T>>>,
+}
+
+impl<T> MacroCommand<T> {
+    pub fn new(name: &str) -> MacroCommand<T> {
+        MacroCommand {
+            name: name.to_owned(),
+            commands: Vec::new(),
+        }
+    }
+
+    /// Add command to the end. It's merged into the last one if that one takes it.
+    pub fn push(&mut self, command: Box<Command<T>>) {
+        if let Some(last) = self.commands.last_mut() {
+            if last.merge_with(&*command) {
+                return;
+            }
+        }
+        self.commands.push(command);
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+impl<T: 'static> Command<T> for MacroCommand<T> {
+    fn execute(&mut self, target: &mut T) {
+        for command in &mut self.commands {
+            command.execute(target);
+        }
+    }
+    fn undo(&mut self, target: &mut T) {
+        for command in self.commands.iter_mut().rev() {
+            command.undo(target);
+        }
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+
+/// Builds a macro out of commands executed while an operation goes on.
+pub struct MacroBuilder<T = Unit> {
+    command: MacroCommand<T>,
+}
+
+impl<T> MacroBuilder<T> {
+    pub fn begin(name: &str) -> MacroBuilder<T> {
+        MacroBuilder { command: MacroCommand::new(name) }
+    }
+
+    /// Execute command now and make it part of the macro.
+    pub fn execute(&mut self, target: &mut T, mut command: Box<Command<T>>) {
+        command.execute(target);
+        self.command.push(command);
+    }
+
+    /// End the operation. Macro is already executed, so it should go to
+    /// `CommandHistory::push`. Returns `None` if nothing was done.
+    pub fn finish(self) -> Option<MacroCommand<T>> {
+        if self.command.is_empty() {
+            None
+        } else {
+            Some(self.command)
+        }
+    }
+
+    /// Abandon the operation, undoing everything done so far.
+    pub fn cancel(mut self, target: &mut T) {
+        for command in self.command.commands.iter_mut().rev() {
+            command.undo(target);
+        }
+    }
+}
+
+
+/// Command for one element of a `Vec`, such as a unit out of a selection. It does nothing if
+/// there is no element at `index`.
+pub struct Indexed<T = Unit> {
+    pub index: usize,
+    command: Box<Command<T>>,
+    /// Whether the element was there for the last `execute`.
+    executed: bool,
+}
+
+impl<T> Indexed<T> {
+    pub fn new(index: usize, command: Box<Command<T>>) -> Indexed<T> {
+        Indexed {
+            index: index,
+            command: command,
+            executed: false,
+        }
+    }
+}
+
+impl<T: 'static> Command<Vec<T>> for Indexed<T> {
+    fn execute(&mut self, targets: &mut Vec<T>) {
+        self.executed = match targets.get_mut(self.index) {
+            Some(target) => {
+                self.command.execute(target);
+                true
+            }
+            None => false,
+        };
+    }
+    fn undo(&mut self, targets: &mut Vec<T>) {
+        if self.executed {
+            if let Some(target) = targets.get_mut(self.index) {
+                self.command.undo(target);
+            }
+            self.executed = false;
+        }
+    }
+    fn name(&self) -> &str {
+        self.command.name()
+    }
+    /// Merges commands for the same element only, both of which found it.
+    fn merge_with(&mut self, next: &Command<Vec<T>>) -> bool {
+        match next.as_any().downcast_ref::<Indexed<T>>() {
+            Some(next) if next.index == self.index && self.executed && next.executed => {
+                self.command.merge_with(&*next.command)
+            }
+            _ => false,
+        }
+    }
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Indexed, MacroBuilder, MacroCommand};
+    use command::{Command, FnCommand, MoveUnit, Unit};
+    use command::history::CommandHistory;
+
+    fn move_to(unit: usize, x: i32, y: i32) -> Box<Command<Vec<Unit>>> {
+        Box::new(Indexed::new(unit, Box::new(MoveUnit::new(x, y))))
+    }
+
+    #[test]
+    fn macro_command() {
+        let mut units: Vec<Unit> = (0..20).map(|i| Unit { x: i, y: 0 }).collect();
+        let mut history = CommandHistory::new(10);
+
+        let mut selection = MacroCommand::new("move selection");
+        for i in 0..units.len() {
+            selection.push(move_to(i, i as i32, 10));
+        }
+        history.execute(&mut units, Box::new(selection));
+        assert!(units.iter().all(|u| u.y == 10));
+        assert!(history.undo_name() == Some("move selection"));
+        assert!(history.undo(&mut units) && !history.can_undo());
+        assert!(units.iter().all(|u| u.y == 0));
+        history.redo(&mut units);
+        assert!(units[19].y == 10);
+
+        // Drag of two units, every step moving both of them.
+        let mut drag = MacroBuilder::begin("drag");
+        for step in 1..6 {
+            drag.execute(&mut units, move_to(0, step, 20));
+            drag.execute(&mut units, move_to(1, step, 20));
+        }
+        assert!(units[0].x == 5 && units[1].y == 20);
+        history.push(Box::new(drag.finish().unwrap()));
+        history.undo(&mut units);
+        assert!(units[0].x == 0 && units[0].y == 10 && units[1].x == 1);
+
+        // Consecutive moves of the same unit merge, other commands are kept apart.
+        let mut drag = MacroBuilder::begin("drag");
+        for x in 0..10 {
+            drag.execute(&mut units, move_to(2, x, x));
+        }
+        drag.execute(&mut units, Box::new(FnCommand::new("noop", Box::new(|_| {}),
+                                                         Box::new(|_| {}))));
+        drag.execute(&mut units, move_to(2, 3, 3));
+        let drag = drag.finish().unwrap();
+        assert!(drag.len() == 3);
+
+        let mut cancelled = MacroBuilder::begin("drag");
+        cancelled.execute(&mut units, move_to(3, -1, -1));
+        cancelled.cancel(&mut units);
+        assert!(units[3].x == 3 && units[3].y == 10);
+        assert!(MacroBuilder::<Unit>::begin("drag").finish().is_none());
+
+        // Unit that isn't there is skipped.
+        let mut stale = MacroBuilder::begin("drag");
+        stale.execute(&mut units, move_to(20, 1, 1));
+        stale.execute(&mut units, move_to(4, 1, 1));
+        let mut stale = stale.finish().unwrap();
+        assert!(units[4].x == 1);
+        stale.undo(&mut units);
+        assert!(units[4].x == 4 && units[4].y == 10);
+    }
+}
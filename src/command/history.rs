@@ -0,0 +1,181 @@
+//! Undo and redo.
+//!
+//! `CommandHistory` executes commands and remembers them, so they can be undone and redone any
+//! number of steps back. Executing a new command after undoing throws away the undone ones, as
+//! there's no longer a single line of history to redo them on. Every command is a step of its own,
+//! except between `begin_merge` and `end_merge`, where command that the previous one merges with
+//! doesn't take a step.
+
+use std::collections::VecDeque;
+
+use command::{Command, Unit};
+
+
+pub struct CommandHistory<T = Unit> {
+    /// Executed commands, oldest first.
+    done: VecDeque<Box<Command<T>>>,
+    /// Undone commands, most recently undone last.
+    undone: Vec<Box<Command<T>>>,
+    capacity: usize,
+    /// Whether merge scope is open.
+    merging: bool,
+    /// Whether the last command was executed in the current merge scope, so others can merge
+    /// into it.
+    mergeable: bool,
+}
+
+impl<T> CommandHistory<T> {
+    /// History remembering at most `capacity` commands. Oldest ones are forgotten first.
+    pub fn new(capacity: usize) -> CommandHistory<T> {
+        CommandHistory {
+            done: VecDeque::with_capacity(capacity),
+            undone: Vec::new(),
+            capacity: capacity,
+            merging: false,
+            mergeable: false,
+        }
+    }
+
+    pub fn execute(&mut self, target: &mut T, mut command: Box<Command<T>>) {
+        command.execute(target);
+        self.push(command);
+    }
+
+    /// Remember command that was already executed, like one built by `MacroBuilder`.
+    pub fn push(&mut self, command: Box<Command<T>>) {
+        self.undone.clear();
+        if self.capacity == 0 {
+            return;
+        }
+        if self.merging && self.mergeable {
+            if let Some(last) = self.done.back_mut() {
+                if last.merge_with(&*command) {
+                    return;
+                }
+            }
+        }
+        if self.done.len() == self.capacity {
+            self.done.pop_front();
+        }
+        self.done.push_back(command);
+        self.mergeable = self.merging;
+    }
+
+    /// Start merging commands executed from now on, like moves during a drag.
+    pub fn begin_merge(&mut self) {
+        self.merging = true;
+        self.mergeable = false;
+    }
+
+    pub fn end_merge(&mut self) {
+        self.merging = false;
+        self.mergeable = false;
+    }
+
+    /// Undo the last executed command. Returns false when there is nothing to undo.
+    pub fn undo(&mut self, target: &mut T) -> bool {
+        self.mergeable = false;
+        match self.done.pop_back() {
+            Some(mut command) => {
+                command.undo(target);
+                self.undone.push(command);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Execute the last undone command again. Returns false when there is nothing to redo.
+    pub fn redo(&mut self, target: &mut T) -> bool {
+        self.mergeable = false;
+        match self.undone.pop() {
+            Some(mut command) => {
+                command.execute(target);
+                self.done.push_back(command);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Name of the command `undo` would undo.
+    pub fn undo_name(&self) -> Option<&str> {
+        self.done.back().map(|c| c.name())
+    }
+
+    /// Name of the command `redo` would execute.
+    pub fn redo_name(&self) -> Option<&str> {
+        self.undone.last().map(|c| c.name())
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.mergeable = false;
+        self.done.clear();
+        self.undone.clear();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::CommandHistory;
+    use command::{FnCommand, MoveUnit, Unit, make_move_unit_cmd};
+
+    #[test]
+    fn history() {
+        let mut unit = Unit { x: 0, y: 0 };
+        let mut history = CommandHistory::new(3);
+        for &(x, y) in &[(1, 1), (2, 2), (3, 3), (4, 4)] {
+            history.execute(&mut unit, Box::new(MoveUnit::new(x, y)));
+        }
+
+        // Only last three moves are remembered.
+        assert!(history.undo(&mut unit) && history.undo(&mut unit) && history.undo(&mut unit));
+        assert!(!history.undo(&mut unit));
+        assert!(unit.x == 1 && unit.y == 1);
+
+        assert!(history.redo(&mut unit));
+        assert!(unit.x == 2 && unit.y == 2);
+
+        // New move replaces whatever was left to redo.
+        let command = FnCommand::from(make_move_unit_cmd(&unit, 7, 0));
+        history.execute(&mut unit, Box::new(command));
+        assert!(history.undo_name() == Some("closure"));
+        assert!(!history.can_redo());
+        assert!(!history.redo(&mut unit));
+        assert!(history.undo(&mut unit));
+        assert!(unit.x == 2 && unit.y == 2);
+        assert!(history.can_undo());
+
+        history.clear();
+        assert!(!history.can_undo() && !history.can_redo());
+    }
+
+    #[test]
+    fn merge() {
+        let mut unit = Unit { x: 0, y: 0 };
+        let mut history = CommandHistory::new(10);
+        history.execute(&mut unit, Box::new(MoveUnit::new(1, 1)));
+
+        // Moves in merge scope are undone in one step, but don't merge with ones before it.
+        history.begin_merge();
+        for x in 2..10 {
+            history.execute(&mut unit, Box::new(MoveUnit::new(x, 5)));
+        }
+        history.end_merge();
+        history.execute(&mut unit, Box::new(MoveUnit::new(0, 0)));
+
+        assert!(history.undo(&mut unit) && unit.x == 9);
+        assert!(history.undo(&mut unit) && unit.x == 1 && unit.y == 1);
+        assert!(history.undo(&mut unit) && !history.can_undo());
+        assert!(unit.x == 0 && unit.y == 0);
+    }
+}
@@ -0,0 +1,250 @@
+//! Binding buttons to commands.
+//!
+//! `InputHandler` knows actions by name, each being a factory that makes a command for the actor
+//! it's given. Buttons are bound to those names, so they can be rebound at runtime and saved to
+//! a plain text config with one binding per line:
+//!
+//! ```text
+//! # button = action
+//! up = move_up
+//! x = move_up
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use command::{Command, MoveUnit, Unit};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Button {
+    A,
+    B,
+    X,
+    Y,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Button {
+    /// Name used in binding configs.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Button::A => "a",
+            Button::B => "b",
+            Button::X => "x",
+            Button::Y => "y",
+            Button::Up => "up",
+            Button::Down => "down",
+            Button::Left => "left",
+            Button::Right => "right",
+        }
+    }
+
+    /// Names are case insensitive.
+    pub fn from_name(name: &str) -> Option<Button> {
+        match &*name.to_lowercase() {
+            "a" => Some(Button::A),
+            "b" => Some(Button::B),
+            "x" => Some(Button::X),
+            "y" => Some(Button::Y),
+            "up" => Some(Button::Up),
+            "down" => Some(Button::Down),
+            "left" => Some(Button::Left),
+            "right" => Some(Button::Right),
+            _ => None,
+        }
+    }
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum BindingErrorKind {
+    UnknownButton(String),
+    UnknownAction(String),
+    /// Line isn't in the `button = action` form.
+    InvalidLine,
+}
+
+/// Error in binding config with the line (counting from 1) on which it happened.
+#[derive(Debug, PartialEq)]
+pub struct BindingError {
+    pub line: usize,
+    pub kind: BindingErrorKind,
+}
+
+impl fmt::Display for BindingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            BindingErrorKind::UnknownButton(ref b) => {
+                write!(f, "line {}: unknown button `{}`", self.line, b)
+            }
+            BindingErrorKind::UnknownAction(ref a) => {
+                write!(f, "line {}: unknown action `{}`", self.line, a)
+            }
+            BindingErrorKind::InvalidLine => {
+                write!(f, "line {}: expected `button = action`", self.line)
+            }
+        }
+    }
+}
+
+
+/// Makes command for the actor.
+pub type CommandFactory<T> = Box<Fn(&T) -> Box<Command<T>>>;
+
+pub struct InputHandler<T = Unit> {
+    actions: HashMap<String, CommandFactory<T>>,
+    bindings: BTreeMap<Button, String>,
+}
+
+impl<T> InputHandler<T> {
+    pub fn new() -> InputHandler<T> {
+        InputHandler {
+            actions: HashMap::new(),
+            bindings: BTreeMap::new(),
+        }
+    }
+
+    /// Add action or replace the one with the same name.
+    pub fn register<F>(&mut self, action: &str, factory: F)
+        where F: Fn(&T) -> Box<Command<T>> + 'static
+    {
+        self.actions.insert(action.to_owned(), Box::new(factory));
+    }
+
+    /// Bind button to the action, replacing whatever it was bound to. Any number of buttons can
+    /// be bound to the same action.
+    pub fn bind(&mut self, button: Button, action: &str) -> Result<(), BindingErrorKind> {
+        if !self.actions.contains_key(action) {
+            return Err(BindingErrorKind::UnknownAction(action.to_owned()));
+        }
+        self.bindings.insert(button, action.to_owned());
+        Ok(())
+    }
+
+    pub fn unbind(&mut self, button: Button) {
+        self.bindings.remove(&button);
+    }
+
+    /// Action the button is bound to.
+    pub fn action(&self, button: Button) -> Option<&str> {
+        self.bindings.get(&button).map(|a| &**a)
+    }
+
+    /// Buttons bound to the action.
+    pub fn buttons(&self, action: &str) -> Vec<Button> {
+        self.bindings.iter().filter(|&(_, a)| a == action).map(|(&b, _)| b).collect()
+    }
+
+    /// Command to execute on actor when button is pressed.
+    pub fn handle(&self, button: Button, actor: &T) -> Option<Box<Command<T>>> {
+        self.bindings
+            .get(&button)
+            .and_then(|action| self.actions.get(action))
+            .map(|factory| factory(actor))
+    }
+
+    /// Replace all bindings with the ones in config. On error bindings stay as they were.
+    pub fn load_bindings(&mut self, config: &str) -> Result<(), BindingError> {
+        let mut bindings = BTreeMap::new();
+        for (i, line) in config.lines().enumerate() {
+            let error = |kind| BindingError { line: i + 1, kind: kind };
+
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=').map(str::trim);
+            let (button, action) = match (parts.next(), parts.next()) {
+                (Some(b), Some(a)) if !b.is_empty() && !a.is_empty() => (b, a),
+                _ => return Err(error(BindingErrorKind::InvalidLine)),
+            };
+            let button = Button::from_name(button)
+                .ok_or_else(|| error(BindingErrorKind::UnknownButton(button.to_owned())))?;
+            if !self.actions.contains_key(action) {
+                return Err(error(BindingErrorKind::UnknownAction(action.to_owned())));
+            }
+            bindings.insert(button, action.to_owned());
+        }
+        self.bindings = bindings;
+        Ok(())
+    }
+
+    /// Config with all bindings that `load_bindings` can read back.
+    pub fn save_bindings(&self) -> String {
+        self.bindings
+            .iter()
+            .map(|(button, action)| format!("{} = {}\n", button.name(), action))
+            .collect()
+    }
+}
+
+impl<T> Default for InputHandler<T> {
+    fn default() -> InputHandler<T> {
+        InputHandler::new()
+    }
+}
+
+impl InputHandler<Unit> {
+    /// Handler with actions moving unit by one in each direction, bound to the arrows.
+    pub fn with_unit_moves() -> InputHandler<Unit> {
+        let mut handler = InputHandler::new();
+        let moves = [("move_up", Button::Up, 0, 1),
+                     ("move_down", Button::Down, 0, -1),
+                     ("move_left", Button::Left, -1, 0),
+                     ("move_right", Button::Right, 1, 0)];
+        for &(action, button, dx, dy) in &moves {
+            handler.register(action, move |unit: &Unit| {
+                Box::new(MoveUnit::new(unit.x + dx, unit.y + dy)) as Box<Command>
+            });
+            handler.bind(button, action).unwrap();
+        }
+        handler
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{BindingError, BindingErrorKind, Button, InputHandler};
+    use command::Unit;
+
+    #[test]
+    fn input_handler() {
+        let mut unit = Unit { x: 0, y: 0 };
+        let mut input = InputHandler::with_unit_moves();
+        input.handle(Button::Up, &unit).unwrap().execute(&mut unit);
+        input.handle(Button::Right, &unit).unwrap().execute(&mut unit);
+        assert!(unit.x == 1 && unit.y == 1);
+        assert!(input.handle(Button::A, &unit).is_none());
+
+        // Both X and the arrow move up.
+        input.bind(Button::X, "move_up").unwrap();
+        input.handle(Button::X, &unit).unwrap().execute(&mut unit);
+        assert!(unit.y == 2);
+        assert!(input.buttons("move_up") == vec![Button::X, Button::Up]);
+
+        input.bind(Button::X, "move_down").unwrap();
+        assert!(input.action(Button::X) == Some("move_down"));
+        assert!(input.bind(Button::Y, "jump") ==
+                Err(BindingErrorKind::UnknownAction("jump".to_owned())));
+
+        let config = input.save_bindings();
+        assert!(config.starts_with("x = move_down\nup = move_up\n"));
+        input.unbind(Button::X);
+        input.load_bindings(&config).unwrap();
+        assert!(input.action(Button::X) == Some("move_down"));
+
+        assert!(input.load_bindings("# arrows\nUP = move_up\n\nstart = move_up") ==
+                Err(BindingError {
+            line: 4,
+            kind: BindingErrorKind::UnknownButton("start".to_owned()),
+        }));
+        assert!(input.load_bindings("a move_up").unwrap_err().kind ==
+                BindingErrorKind::InvalidLine);
+        assert!(input.save_bindings() == config);
+    }
+}
@@ -0,0 +1,371 @@
+//! Lockstep multiplayer.
+//!
+//! Peers send each other commands instead of state. Every player's commands for a tick are
+//! scheduled `delay` ticks ahead, and a tick is executed only once commands of all players for it
+//! arrived, always in the same order, so all peers go through the same states. After every tick
+//! peers exchange a hash of their units; different hashes mean the game desynced.
+//!
+//! Peers talk through a `Transport`. `Loopback` connects peers in the same process.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use command::Unit;
+use command::replay::CommandRecord;
+
+pub type PlayerId = usize;
+
+/// Commands of one player for a tick, with the unit each of them is for.
+pub type Input = Vec<(usize, CommandRecord)>;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// Sent for every tick even if empty, so others know they have all commands of the player.
+    Commands {
+        player: PlayerId,
+        tick: u64,
+        commands: Input,
+    },
+    /// Hash of units after executing the tick.
+    Hash {
+        player: PlayerId,
+        tick: u64,
+        hash: u64,
+    },
+}
+
+/// Delivers messages to all other peers.
+pub trait Transport {
+    fn send(&mut self, message: Message);
+    /// Next message that arrived, without waiting for one.
+    fn receive(&mut self) -> Option<Message>;
+}
+
+/// Transport over channels to peers in the same process.
+pub struct Loopback {
+    peers: Vec<Sender<Message>>,
+    inbox: Receiver<Message>,
+}
+
+impl Loopback {
+    /// Transports for `count` peers, each one connected to all the others.
+    pub fn connect(count: usize) -> Vec<Loopback> {
+        let (senders, inboxes): (Vec<_>, Vec<_>) = (0..count).map(|_| mpsc::channel()).unzip();
+        inboxes.into_iter()
+            .enumerate()
+            .map(|(i, inbox)| {
+                Loopback {
+                    peers: senders.iter()
+                        .enumerate()
+                        .filter(|&(j, _)| j != i)
+                        .map(|(_, sender)| sender.clone())
+                        .collect(),
+                    inbox: inbox,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Transport for Loopback {
+    /// Peers that went away are skipped.
+    fn send(&mut self, message: Message) {
+        for peer in &self.peers {
+            let _ = peer.send(message.clone());
+        }
+    }
+    fn receive(&mut self) -> Option<Message> {
+        self.inbox.try_recv().ok()
+    }
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum LockstepError {
+    /// Player's units ended up different than ours after the tick.
+    Desync { tick: u64, player: PlayerId },
+    UnknownUnit {
+        tick: u64,
+        player: PlayerId,
+        unit: usize,
+    },
+    /// Player id out of range, either of a message or the local one.
+    UnknownPlayer(PlayerId),
+}
+
+impl fmt::Display for LockstepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LockstepError::Desync { tick, player } => {
+                write!(f, "tick {}: player {} desynced", tick, player)
+            }
+            LockstepError::UnknownUnit { tick, player, unit } => {
+                write!(f, "tick {}: player {} commanded unknown unit {}", tick, player, unit)
+            }
+            LockstepError::UnknownPlayer(player) => {
+                write!(f, "unknown player {}", player)
+            }
+        }
+    }
+}
+
+
+/// FNV-1a hash of unit positions. Same on every platform, unlike `std` hashers.
+pub fn state_hash(units: &[Unit]) -> u64 {
+    let mut hash = 0xcbf29ce484222325;
+    for unit in units {
+        for &n in &[unit.x, unit.y] {
+            for i in 0..4 {
+                hash ^= (n >> (i * 8)) as u8 as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+    }
+    hash
+}
+
+
+pub struct Lockstep<T: Transport> {
+    player: PlayerId,
+    players: usize,
+    delay: u64,
+    transport: T,
+    /// Next tick to execute.
+    tick: u64,
+    /// Local commands waiting to be sent.
+    local: Input,
+    /// Inputs of every player for ticks not executed yet.
+    inputs: BTreeMap<u64, Vec<Option<Input>>>,
+    /// Hashes of executed ticks and how many players confirmed them.
+    hashes: BTreeMap<u64, (u64, usize)>,
+    /// Hashes of ticks not executed yet.
+    early: BTreeMap<u64, Vec<(PlayerId, u64)>>,
+}
+
+impl<T: Transport> Lockstep<T> {
+    /// Commands issued are executed `delay` ticks later, giving them time to reach other peers.
+    /// Players are numbered from zero, so `player` has to be less than `players`.
+    pub fn new(player: PlayerId,
+               players: usize,
+               delay: u64,
+               transport: T)
+               -> Result<Lockstep<T>, LockstepError> {
+        if player >= players {
+            return Err(LockstepError::UnknownPlayer(player));
+        }
+        let mut lockstep = Lockstep {
+            player: player,
+            players: players,
+            delay: delay,
+            transport: transport,
+            tick: 0,
+            local: Vec::new(),
+            inputs: BTreeMap::new(),
+            hashes: BTreeMap::new(),
+            early: BTreeMap::new(),
+        };
+        // Nobody could issue commands for the first ticks.
+        for tick in 0..delay {
+            lockstep.send_input(tick);
+        }
+        Ok(lockstep)
+    }
+
+    pub fn player(&self) -> PlayerId {
+        self.player
+    }
+
+    /// Next tick to execute.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn issue(&mut self, unit: usize, command: CommandRecord) {
+        self.local.push((unit, command));
+    }
+
+    /// Send issued commands and execute the next tick if all inputs for it arrived. Returns
+    /// whether it did, false means it's still waiting for other players. Tick with a command
+    /// for a unit that isn't there is not executed at all.
+    pub fn step(&mut self, units: &mut [Unit]) -> Result<bool, LockstepError> {
+        let scheduled = self.tick + self.delay;
+        if !self.has_input(scheduled, self.player) {
+            self.send_input(scheduled);
+        }
+        while let Some(message) = self.transport.receive() {
+            self.handle(message)?;
+        }
+
+        let tick = self.tick;
+        if !(0..self.players).all(|player| self.has_input(tick, player)) {
+            return Ok(false);
+        }
+        let inputs = self.inputs.remove(&tick).unwrap_or_default();
+        for (player, input) in inputs.iter().enumerate() {
+            let commands = input.as_ref().map_or(&[][..], |commands| &commands[..]);
+            if let Some(&(unit, _)) = commands.iter().find(|&&(unit, _)| unit >= units.len()) {
+                self.inputs.insert(tick, inputs);
+                return Err(LockstepError::UnknownUnit {
+                    tick: tick,
+                    player: player,
+                    unit: unit,
+                });
+            }
+        }
+        for input in inputs {
+            for (unit, command) in input.unwrap_or_default() {
+                command.to_command().execute(&mut units[unit]);
+            }
+        }
+
+        let hash = state_hash(units);
+        self.transport.send(Message::Hash {
+            player: self.player,
+            tick: tick,
+            hash: hash,
+        });
+        if self.players > 1 {
+            self.hashes.insert(tick, (hash, 0));
+        }
+        self.tick += 1;
+        for (player, remote) in self.early.remove(&tick).unwrap_or_default() {
+            self.check_hash(tick, player, remote)?;
+        }
+        Ok(true)
+    }
+
+    fn has_input(&self, tick: u64, player: PlayerId) -> bool {
+        self.inputs.get(&tick).map_or(false, |inputs| inputs[player].is_some())
+    }
+
+    fn set_input(&mut self, tick: u64, player: PlayerId, input: Input) {
+        let players = self.players;
+        self.inputs.entry(tick).or_insert_with(|| vec![None; players])[player] = Some(input);
+    }
+
+    fn send_input(&mut self, tick: u64) {
+        let commands = self.local.split_off(0);
+        self.transport.send(Message::Commands {
+            player: self.player,
+            tick: tick,
+            commands: commands.clone(),
+        });
+        let player = self.player;
+        self.set_input(tick, player, commands);
+    }
+
+    fn handle(&mut self, message: Message) -> Result<(), LockstepError> {
+        match message {
+            Message::Commands { player, tick, commands } => {
+                if player >= self.players {
+                    return Err(LockstepError::UnknownPlayer(player));
+                }
+                if tick >= self.tick {
+                    self.set_input(tick, player, commands);
+                }
+            }
+            Message::Hash { player, tick, hash } => {
+                if player >= self.players {
+                    return Err(LockstepError::UnknownPlayer(player));
+                }
+                if tick < self.tick {
+                    self.check_hash(tick, player, hash)?;
+                } else {
+                    self.early.entry(tick).or_insert_with(Vec::new).push((player, hash));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_hash(&mut self,
+                  tick: u64,
+                  player: PlayerId,
+                  remote: u64)
+                  -> Result<(), LockstepError> {
+        let confirmed = match self.hashes.get_mut(&tick) {
+            Some(&mut (hash, ref mut confirmations)) => {
+                if hash != remote {
+                    return Err(LockstepError::Desync {
+                        tick: tick,
+                        player: player,
+                    });
+                }
+                *confirmations += 1;
+                *confirmations + 1 == self.players
+            }
+            None => false,
+        };
+        if confirmed {
+            self.hashes.remove(&tick);
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Lockstep, LockstepError, Loopback, state_hash};
+    use command::Unit;
+    use command::replay::CommandRecord;
+
+    #[test]
+    fn lockstep() {
+        let mut transports = Loopback::connect(2);
+        let mut second = Lockstep::new(1, 2, 2, transports.pop().unwrap()).unwrap();
+        let mut first = Lockstep::new(0, 2, 2, transports.pop().unwrap()).unwrap();
+        let start = vec![Unit { x: 0, y: 0 }, Unit { x: 0, y: 0 }];
+        let mut first_units = start.clone();
+        let mut second_units = start.clone();
+
+        first.issue(0, CommandRecord::Move { x: 5, y: 5 });
+        second.issue(1, CommandRecord::Move { x: -5, y: 5 });
+        second.issue(0, CommandRecord::Move { x: 1, y: 1 });
+
+        // First ticks are known to be empty, then first has to wait for second.
+        assert!(first.step(&mut first_units) == Ok(true));
+        assert!(first.step(&mut first_units) == Ok(true));
+        assert!(first.step(&mut first_units) == Ok(false));
+        assert!(first_units == start);
+
+        for _ in 0..3 {
+            assert!(second.step(&mut second_units) == Ok(true));
+        }
+        assert!(first.step(&mut first_units) == Ok(true));
+        assert!(first.tick() == 3 && second.tick() == 3);
+        // Commands of the first player go first.
+        assert!(first_units == second_units);
+        assert!(first_units[0] == Unit { x: 1, y: 1 });
+        assert!(first_units[1] == Unit { x: -5, y: 5 });
+
+        // Second one gets out of sync and first notices once it gets its hash.
+        second_units[1].x = 0;
+        assert!(second.step(&mut second_units) == Ok(true));
+        assert!(first.step(&mut first_units) ==
+                Err(LockstepError::Desync { tick: 3, player: 1 }));
+
+        assert!(state_hash(&start) != state_hash(&first_units));
+
+        // Tick commanding a missing unit is left whole.
+        let mut transports = Loopback::connect(1);
+        let mut alone = Lockstep::new(0, 1, 0, transports.pop().unwrap()).unwrap();
+        let mut units = start.clone();
+        alone.issue(0, CommandRecord::Move { x: 5, y: 5 });
+        alone.issue(2, CommandRecord::Move { x: 1, y: 1 });
+        for _ in 0..2 {
+            assert!(alone.step(&mut units) ==
+                    Err(LockstepError::UnknownUnit {
+                tick: 0,
+                player: 0,
+                unit: 2,
+            }));
+            assert!(alone.tick() == 0 && units == start);
+        }
+
+        let transport = Loopback::connect(2).pop().unwrap();
+        assert!(Lockstep::new(2, 2, 2, transport).err() == Some(LockstepError::UnknownPlayer(2)));
+    }
+}
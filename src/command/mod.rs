@@ -0,0 +1,198 @@
+//! Command Pattern
+//! http://gameprogrammingpatterns.com/command.html
+
+pub mod actor;
+pub mod composite;
+pub mod history;
+pub mod input;
+pub mod lockstep;
+pub mod replay;
+
+use std::any::Any;
+
+use command::actor::Movable;
+
+/// Generic game unit that has it's own 2D position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Unit {
+    fn move_to(&mut self, x: i32, y: i32) {
+        println!("Moving player to: ({}, {})", x, y);
+        self.x = x;
+        self.y = y;
+    }
+}
+
+impl Movable for Unit {
+    fn position(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+    fn set_position(&mut self, x: i32, y: i32) {
+        self.move_to(x, y);
+    }
+}
+
+
+// Functional Version
+/// Command together with the action that undoes it.
+pub type UnitCommand = (Box<Fn(&mut Unit)>, Box<Fn(&mut Unit)>);
+
+/// Return 2 closures one with command to move Player and other one that will undo that action.
+pub fn make_move_unit_cmd(unit: &Unit, x: i32, y: i32) -> UnitCommand {
+    let Unit { x: last_x, y: last_y } = *unit;
+    (Box::new(move |unit: &mut Unit| unit.move_to(x, y)),
+     Box::new(move |unit: &mut Unit| unit.move_to(last_x, last_y)))
+}
+
+
+// Object Version
+/// Undoable action on a target, `Unit` unless said otherwise.
+pub trait Command<T = Unit> {
+    fn execute(&mut self, target: &mut T);
+    /// Revert the last `execute`.
+    fn undo(&mut self, target: &mut T);
+    fn name(&self) -> &str;
+    /// Absorb `next` command, which was just executed after this one, so both are undone as a
+    /// single step. Returns whether it did.
+    fn merge_with(&mut self, _next: &Command<T>) -> bool {
+        false
+    }
+    /// Lets `merge_with` find out what the other command is.
+    fn as_any(&self) -> &Any;
+}
+
+/// Move unit, or anything else `Movable`, to a position. Remembers where the unit was when
+/// executed, so it undoes correctly whatever happened before. Moves following each other can be
+/// merged into one that undoes to where the unit was before the first of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveUnit {
+    pub x: i32,
+    pub y: i32,
+    previous: Option<(i32, i32)>,
+}
+
+impl MoveUnit {
+    pub fn new(x: i32, y: i32) -> MoveUnit {
+        MoveUnit {
+            x: x,
+            y: y,
+            previous: None,
+        }
+    }
+}
+
+impl<T: Movable + 'static> Command<T> for MoveUnit {
+    fn execute(&mut self, unit: &mut T) {
+        self.previous = Some(unit.position());
+        unit.set_position(self.x, self.y);
+    }
+    fn undo(&mut self, unit: &mut T) {
+        if let Some((x, y)) = self.previous.take() {
+            unit.set_position(x, y);
+        }
+    }
+    fn name(&self) -> &str {
+        "move unit"
+    }
+    fn merge_with(&mut self, next: &Command<T>) -> bool {
+        match next.as_any().downcast_ref::<MoveUnit>() {
+            Some(next) => {
+                self.x = next.x;
+                self.y = next.y;
+                true
+            }
+            None => false,
+        }
+    }
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+/// Closure pair usable as a `Command`.
+pub struct FnCommand<T> {
+    name: String,
+    execute: Box<Fn(&mut T)>,
+    undo: Box<Fn(&mut T)>,
+}
+
+impl<T> FnCommand<T> {
+    pub fn new(name: &str, execute: Box<Fn(&mut T)>, undo: Box<Fn(&mut T)>) -> FnCommand<T> {
+        FnCommand {
+            name: name.to_owned(),
+            execute: execute,
+            undo: undo,
+        }
+    }
+}
+
+/// Wraps pairs such as the one from `make_move_unit_cmd`.
+impl<T> From<(Box<Fn(&mut T)>, Box<Fn(&mut T)>)> for FnCommand<T> {
+    fn from((execute, undo): (Box<Fn(&mut T)>, Box<Fn(&mut T)>)) -> FnCommand<T> {
+        FnCommand::new("closure", execute, undo)
+    }
+}
+
+impl<T: 'static> Command<T> for FnCommand<T> {
+    fn execute(&mut self, target: &mut T) {
+        (self.execute)(target);
+    }
+    fn undo(&mut self, target: &mut T) {
+        (self.undo)(target);
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Command, FnCommand, MoveUnit, Unit, make_move_unit_cmd};
+
+    #[test]
+    pub fn command() {
+        let mut unit = Unit { x: 0, y: 0 };
+        let (cmd, undo) = make_move_unit_cmd(&unit, 5, 10);
+        cmd(&mut unit);
+        assert!(unit.x == 5);
+        assert!(unit.y == 10);
+        undo(&mut unit);
+        assert!(unit.x == 0);
+        assert!(unit.y == 0);
+    }
+
+    #[test]
+    pub fn command_object() {
+        let mut unit = Unit { x: 0, y: 0 };
+        let mut jump: Box<Command> = Box::new(MoveUnit::new(5, 10));
+        jump.execute(&mut unit);
+        assert!(unit.x == 5 && unit.y == 10);
+
+        let mut closure: Box<Command> = Box::new(FnCommand::from(make_move_unit_cmd(&unit, 1, 1)));
+        closure.execute(&mut unit);
+        assert!(unit.x == 1 && unit.y == 1);
+        closure.undo(&mut unit);
+        jump.undo(&mut unit);
+        assert!(unit.x == 0 && unit.y == 0);
+
+        assert!(jump.name() == "move unit");
+        assert!(closure.name() == "closure");
+        assert!(jump.as_any().downcast_ref::<MoveUnit>() == Some(&MoveUnit::new(5, 10)));
+        assert!(!jump.merge_with(&*closure));
+
+        jump.execute(&mut unit);
+        let mut step = MoveUnit::new(6, 10);
+        step.execute(&mut unit);
+        assert!(jump.merge_with(&step));
+        jump.undo(&mut unit);
+        assert!(unit.x == 0 && unit.y == 0);
+    }
+}
@@ -0,0 +1,541 @@
+//! Recording and replaying commands.
+//!
+//! `Recorder` executes commands on units and writes down on which tick each of them happened.
+//! Finished `Replay` can be saved as text or compact binary and given to a `Player`, which
+//! applies the same commands on the same ticks to fresh units and checks they end up where
+//! they did when recording. Ticks are fixed updates, so `Player` can be run by the game loop.
+//!
+//! Text format has one command per line, followed by number of ticks and final unit positions:
+//!
+//! ```text
+//! # tick unit command arguments
+//! 0 0 move 5 10
+//! 3 1 move -2 4
+//! ticks 10
+//! final 5 10
+//! final -2 4
+//! ```
+//!
+//! Binary format starts with magic bytes `GPPR` and version byte, all numbers after that are
+//! LEB128 varints with signed ones zigzag encoded. Ticks of commands are stored as difference
+//! from the previous one.
+
+use std::cmp;
+use std::fmt;
+use std::time::Duration;
+
+use command::{Command, MoveUnit, Unit};
+use game_loop::Game;
+
+pub const MAGIC: [u8; 4] = [b'G', b'P', b'P', b'R'];
+pub const VERSION: u8 = 1;
+
+const TAG_MOVE: u8 = 1;
+
+
+/// Command in a form that can be saved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandRecord {
+    Move { x: i32, y: i32 },
+}
+
+impl CommandRecord {
+    pub fn to_command(&self) -> Box<Command> {
+        match *self {
+            CommandRecord::Move { x, y } => Box::new(MoveUnit::new(x, y)),
+        }
+    }
+
+    /// Record of command if it's one of those that can be recorded.
+    pub fn from_command(command: &Command) -> Option<CommandRecord> {
+        command.as_any()
+            .downcast_ref::<MoveUnit>()
+            .map(|m| CommandRecord::Move { x: m.x, y: m.y })
+    }
+}
+
+impl fmt::Display for CommandRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CommandRecord::Move { x, y } => write!(f, "move {} {}", x, y),
+        }
+    }
+}
+
+/// Command applied to a unit on a tick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Record {
+    pub tick: u64,
+    pub unit: usize,
+    pub command: CommandRecord,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayError {
+    /// Line of text replay that couldn't be read.
+    InvalidLine(usize),
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    InvalidCommand(u8),
+    /// Commands are not ordered by tick or come after the end of replay. Tick that doesn't fit
+    /// in `u64` is reported as the largest one.
+    InvalidTick(u64),
+    UnknownUnit { tick: u64, unit: usize },
+    /// Unit count is different from the one replay was recorded with.
+    UnitCount { expected: usize, found: usize },
+    Mismatch {
+        unit: usize,
+        expected: Unit,
+        found: Unit,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplayError::InvalidLine(line) => write!(f, "line {}: invalid replay line", line),
+            ReplayError::BadMagic => write!(f, "not a replay"),
+            ReplayError::UnsupportedVersion(v) => write!(f, "replay version {} not supported", v),
+            ReplayError::Truncated => write!(f, "replay is truncated"),
+            ReplayError::InvalidCommand(tag) => write!(f, "unknown command {}", tag),
+            ReplayError::InvalidTick(tick) => write!(f, "command on tick {} is out of order", tick),
+            ReplayError::UnknownUnit { tick, unit } => {
+                write!(f, "tick {}: there is no unit {}", tick, unit)
+            }
+            ReplayError::UnitCount { expected, found } => {
+                write!(f, "replay needs {} units but got {}", expected, found)
+            }
+            ReplayError::Mismatch { unit, expected, found } => {
+                write!(f,
+                       "unit {} ended at ({}, {}) instead of ({}, {})",
+                       unit,
+                       found.x,
+                       found.y,
+                       expected.x,
+                       expected.y)
+            }
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Replay {
+    /// Ordered by tick.
+    pub records: Vec<Record>,
+    /// Number of ticks recorded.
+    pub ticks: u64,
+    /// Units at the end of recording.
+    pub final_state: Vec<Unit>,
+}
+
+impl Replay {
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for record in &self.records {
+            text.push_str(&format!("{} {} {}\n", record.tick, record.unit, record.command));
+        }
+        text.push_str(&format!("ticks {}\n", self.ticks));
+        for unit in &self.final_state {
+            text.push_str(&format!("final {} {}\n", unit.x, unit.y));
+        }
+        text
+    }
+
+    /// Read replay from text. Empty lines and everything after `#` are ignored.
+    pub fn from_text(text: &str) -> Result<Replay, ReplayError> {
+        let mut replay = Replay::default();
+        for (i, line) in text.lines().enumerate() {
+            let invalid = || ReplayError::InvalidLine(i + 1);
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            match (words[0], words.len()) {
+                ("ticks", 2) => replay.ticks = words[1].parse().map_err(|_| invalid())?,
+                ("final", 3) => {
+                    let x = words[1].parse().map_err(|_| invalid())?;
+                    let y = words[2].parse().map_err(|_| invalid())?;
+                    replay.final_state.push(Unit { x: x, y: y });
+                }
+                _ if words.len() == 5 && words[2] == "move" => {
+                    let number = |n: &str| n.parse().map_err(|_| invalid());
+                    replay.records.push(Record {
+                        tick: words[0].parse().map_err(|_| invalid())?,
+                        unit: words[1].parse().map_err(|_| invalid())?,
+                        command: CommandRecord::Move {
+                            x: number(words[3])?,
+                            y: number(words[4])?,
+                        },
+                    });
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        replay.check_ticks()?;
+        Ok(replay)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        put_varint(&mut out, self.records.len() as u64);
+        let mut previous = 0;
+        for record in &self.records {
+            put_varint(&mut out, record.tick - previous);
+            previous = record.tick;
+            put_varint(&mut out, record.unit as u64);
+            match record.command {
+                CommandRecord::Move { x, y } => {
+                    out.push(TAG_MOVE);
+                    put_varint(&mut out, zigzag(x));
+                    put_varint(&mut out, zigzag(y));
+                }
+            }
+        }
+        put_varint(&mut out, self.ticks);
+        put_varint(&mut out, self.final_state.len() as u64);
+        for unit in &self.final_state {
+            put_varint(&mut out, zigzag(unit.x));
+            put_varint(&mut out, zigzag(unit.y));
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Replay, ReplayError> {
+        if bytes.len() < MAGIC.len() + 1 || bytes[..MAGIC.len()] != MAGIC {
+            return Err(ReplayError::BadMagic);
+        }
+        if bytes[MAGIC.len()] != VERSION {
+            return Err(ReplayError::UnsupportedVersion(bytes[MAGIC.len()]));
+        }
+        let mut reader = Reader {
+            bytes: bytes,
+            pos: MAGIC.len() + 1,
+        };
+
+        let mut replay = Replay::default();
+        let mut tick: u64 = 0;
+        for _ in 0..reader.varint()? {
+            let delta = reader.varint()?;
+            tick = tick.checked_add(delta).ok_or(ReplayError::InvalidTick(u64::max_value()))?;
+            let unit = reader.varint()? as usize;
+            let command = match reader.byte()? {
+                TAG_MOVE => {
+                    CommandRecord::Move {
+                        x: unzigzag(reader.varint()?),
+                        y: unzigzag(reader.varint()?),
+                    }
+                }
+                tag => return Err(ReplayError::InvalidCommand(tag)),
+            };
+            replay.records.push(Record {
+                tick: tick,
+                unit: unit,
+                command: command,
+            });
+        }
+        replay.ticks = reader.varint()?;
+        for _ in 0..reader.varint()? {
+            let x = unzigzag(reader.varint()?);
+            let y = unzigzag(reader.varint()?);
+            replay.final_state.push(Unit { x: x, y: y });
+        }
+        replay.check_ticks()?;
+        Ok(replay)
+    }
+
+    fn check_ticks(&self) -> Result<(), ReplayError> {
+        let mut previous = 0;
+        for record in &self.records {
+            if record.tick < previous || record.tick >= self.ticks {
+                return Err(ReplayError::InvalidTick(record.tick));
+            }
+            previous = record.tick;
+        }
+        Ok(())
+    }
+}
+
+
+/// Executes commands on units and records them.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    tick: u64,
+    records: Vec<Record>,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder::default()
+    }
+
+    pub fn current_tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Move on to the next tick. Call once after every fixed update.
+    pub fn tick(&mut self) {
+        self.tick += 1;
+    }
+
+    /// Execute command on the unit and record it for the current tick. Nothing is recorded if
+    /// there is no such unit.
+    pub fn execute(&mut self,
+                   units: &mut [Unit],
+                   unit: usize,
+                   command: CommandRecord)
+                   -> Result<(), ReplayError> {
+        let target = units.get_mut(unit).ok_or(ReplayError::UnknownUnit {
+                tick: self.tick,
+                unit: unit,
+            })?;
+        command.to_command().execute(target);
+        self.records.push(Record {
+            tick: self.tick,
+            unit: unit,
+            command: command,
+        });
+        Ok(())
+    }
+
+    /// Finish recording with units as they are at the end. Tick with commands already recorded
+    /// counts even if `tick` wasn't called after them.
+    pub fn finish(self, units: &[Unit]) -> Replay {
+        let open = self.records.last().map_or(0, |record| record.tick + 1);
+        Replay {
+            ticks: cmp::max(self.tick, open),
+            records: self.records,
+            final_state: units.to_vec(),
+        }
+    }
+}
+
+
+/// Replays commands on units, one tick per update.
+#[derive(Debug)]
+pub struct Player {
+    pub units: Vec<Unit>,
+    replay: Replay,
+    next: usize,
+    tick: u64,
+    error: Option<ReplayError>,
+}
+
+impl Player {
+    /// Player for units in the same state they were in when recording started.
+    pub fn new(replay: Replay, units: Vec<Unit>) -> Player {
+        Player {
+            units: units,
+            replay: replay,
+            next: 0,
+            tick: 0,
+            error: None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tick >= self.replay.ticks
+    }
+
+    /// First error replay ran into when run by the game loop.
+    pub fn error(&self) -> Option<&ReplayError> {
+        self.error.as_ref()
+    }
+
+    /// Apply all commands of the current tick and move on to the next one.
+    pub fn step(&mut self) -> Result<(), ReplayError> {
+        while let Some(record) = self.replay.records.get(self.next).cloned() {
+            if record.tick != self.tick {
+                break;
+            }
+            let unit = self.units.get_mut(record.unit).ok_or(ReplayError::UnknownUnit {
+                    tick: record.tick,
+                    unit: record.unit,
+                })?;
+            record.command.to_command().execute(unit);
+            self.next += 1;
+        }
+        self.tick += 1;
+        Ok(())
+    }
+
+    /// Play the rest of replay and verify the outcome.
+    pub fn play(&mut self) -> Result<(), ReplayError> {
+        while !self.is_finished() {
+            self.step()?;
+        }
+        self.verify()
+    }
+
+    /// Check units ended up where they did when recording.
+    pub fn verify(&self) -> Result<(), ReplayError> {
+        let expected = &self.replay.final_state;
+        if expected.len() != self.units.len() {
+            return Err(ReplayError::UnitCount {
+                expected: expected.len(),
+                found: self.units.len(),
+            });
+        }
+        match expected.iter().zip(&self.units).position(|(e, f)| e != f) {
+            Some(unit) => {
+                Err(ReplayError::Mismatch {
+                    unit: unit,
+                    expected: expected[unit],
+                    found: self.units[unit],
+                })
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Game for Player {
+    fn process_input(&mut self) {}
+    fn update(&mut self, _dt: Duration) {
+        if self.error.is_none() && !self.is_finished() {
+            self.error = self.step().err();
+        }
+    }
+    fn render(&mut self, _alpha: f32) {}
+    fn should_quit(&self) -> bool {
+        self.is_finished() || self.error.is_some()
+    }
+}
+
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn zigzag(value: i32) -> u64 {
+    ((value << 1) ^ (value >> 31)) as u32 as u64
+}
+
+fn unzigzag(value: u64) -> i32 {
+    let value = value as u32;
+    (value >> 1) as i32 ^ -((value & 1) as i32)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, ReplayError> {
+        let byte = *self.bytes.get(self.pos).ok_or(ReplayError::Truncated)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u64, ReplayError> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as u64) << shift;
+            }
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{CommandRecord, Player, Recorder, Replay, ReplayError};
+    use command::{MoveUnit, Unit};
+    use game_loop::clock::ManualClock;
+    use game_loop::headless::Headless;
+
+    fn fresh() -> Vec<Unit> {
+        vec![Unit { x: 0, y: 0 }, Unit { x: 3, y: 3 }]
+    }
+
+    #[test]
+    fn replay() {
+        let mut units = fresh();
+        let mut recorder = Recorder::new();
+        recorder.execute(&mut units, 0, CommandRecord::Move { x: 5, y: 10 }).unwrap();
+        for _ in 0..3 {
+            recorder.tick();
+        }
+        recorder.execute(&mut units, 1, CommandRecord::Move { x: -2, y: 4 }).unwrap();
+        recorder.execute(&mut units, 0, CommandRecord::Move { x: 300, y: -70000 }).unwrap();
+        recorder.tick();
+        let replay = recorder.finish(&units);
+        assert!(replay.ticks == 4 && replay.records[1].tick == 3);
+
+        let text = replay.to_text();
+        assert!(text.starts_with("0 0 move 5 10\n3 1 move -2 4\n"));
+        assert!(Replay::from_text(&text).unwrap() == replay);
+        let bytes = replay.to_bytes();
+        assert!(bytes.len() < text.len());
+        assert!(Replay::from_bytes(&bytes).unwrap() == replay);
+        assert!(Replay::from_bytes(&bytes[..bytes.len() - 1]) == Err(ReplayError::Truncated));
+        assert!(Replay::from_text("0 0 jump\n") == Err(ReplayError::InvalidLine(1)));
+
+        // Tick deltas adding up past the largest tick.
+        let mut overflow = b"GPPR\x01\x02".to_vec();
+        for _ in 0..2 {
+            overflow.extend_from_slice(&[0xff; 9]);
+            overflow.extend_from_slice(&[0x01, 0x00, 0x01, 0x00, 0x00]);
+        }
+        assert!(Replay::from_bytes(&overflow) == Err(ReplayError::InvalidTick(u64::max_value())));
+
+        let mut player = Player::new(replay.clone(), fresh());
+        player.play().unwrap();
+        assert!(player.units == units);
+
+        // Same replay driven by the game loop one tick per update.
+        let headless = Headless::with_clock(ManualClock::new());
+        let run = headless.until(Player::new(replay.clone(), fresh()),
+                                 |player| player.is_finished());
+        assert!(run.stats.updates == 4);
+        assert!(run.game.verify() == Ok(()));
+
+        // Unit left untouched by replay ends up somewhere else than it was recorded.
+        let mut tampered = replay.clone();
+        tampered.records.remove(1);
+        assert!(Player::new(tampered, fresh()).play() ==
+                Err(ReplayError::Mismatch {
+            unit: 1,
+            expected: Unit { x: -2, y: 4 },
+            found: Unit { x: 3, y: 3 },
+        }));
+        assert!(Player::new(replay, vec![]).play() ==
+                Err(ReplayError::UnknownUnit { tick: 0, unit: 0 }));
+
+        assert!(CommandRecord::from_command(&MoveUnit::new(1, 2)) ==
+                Some(CommandRecord::Move { x: 1, y: 2 }));
+    }
+
+    #[test]
+    fn finish_open_tick() {
+        let mut units = fresh();
+        let mut recorder = Recorder::new();
+        recorder.tick();
+        recorder.execute(&mut units, 1, CommandRecord::Move { x: 7, y: 7 }).unwrap();
+        assert!(recorder.execute(&mut units, 2, CommandRecord::Move { x: 1, y: 1 }) ==
+                Err(ReplayError::UnknownUnit { tick: 1, unit: 2 }));
+        let replay = recorder.finish(&units);
+        assert!(replay.ticks == 2 && replay.records.len() == 1);
+
+        assert!(Replay::from_text(&replay.to_text()).unwrap() == replay);
+        assert!(Replay::from_bytes(&replay.to_bytes()).unwrap() == replay);
+        assert!(Player::new(replay, fresh()).play() == Ok(()));
+        assert!(Recorder::new().finish(&[]).ticks == 0);
+    }
+}
@@ -8,6 +8,10 @@
 //! Based on:
 //! http://www.gamedev.net/page/resources/_/technical/game-programming/implementing-component-entity-systems-r3382
 
+use std::time::Duration;
+
+use game_loop::Game;
+use game_loop::interpolate::Interpolate;
 
 // ================================================================================================
 // Components
@@ -28,12 +32,54 @@ pub struct Velocity {
     y: f32,
 }
 
+impl Velocity {
+    pub fn new(x: f32, y: f32) -> Velocity {
+        Velocity { x: x, y: y }
+    }
+
+    pub fn x(&self) -> f32 {
+        self.x
+    }
+
+    pub fn y(&self) -> f32 {
+        self.y
+    }
+}
+
+impl Interpolate for Velocity {
+    fn interpolate(&self, other: &Velocity, alpha: f32) -> Velocity {
+        Velocity::new(self.x.interpolate(&other.x, alpha),
+                      self.y.interpolate(&other.y, alpha))
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct Displacement {
     x: f32,
     y: f32,
 }
 
+impl Displacement {
+    pub fn new(x: f32, y: f32) -> Displacement {
+        Displacement { x: x, y: y }
+    }
+
+    pub fn x(&self) -> f32 {
+        self.x
+    }
+
+    pub fn y(&self) -> f32 {
+        self.y
+    }
+}
+
+impl Interpolate for Displacement {
+    fn interpolate(&self, other: &Displacement, alpha: f32) -> Displacement {
+        Displacement::new(self.x.interpolate(&other.x, alpha),
+                          self.y.interpolate(&other.y, alpha))
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct Appearance {
     name: String,
@@ -177,3 +223,14 @@ pub fn render_system(world: &mut World) {
         println!("Drawing {} at {:?}", app.name, disp);
     }
 }
+
+/// World advances by one movement step every update, no matter how long it is.
+impl Game for World {
+    fn process_input(&mut self) {}
+    fn update(&mut self, _dt: Duration) {
+        movement_system(self);
+    }
+    fn render(&mut self, _alpha: f32) {
+        render_system(self);
+    }
+}
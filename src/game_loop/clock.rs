@@ -0,0 +1,104 @@
+//! Time sources for the game loop.
+//!
+//! `GameLoop` never asks the system for time itself, it reads it from a `Clock`. Real games use
+//! `SystemClock` while tests and replays use `ManualClock`, which only moves when told to, so
+//! every run sees exactly the same times.
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+pub trait Clock {
+    /// Time since some fixed point in the past. Never goes backwards.
+    fn now(&self) -> Duration;
+    /// Wait for given time to pass.
+    fn sleep(&self, duration: Duration);
+}
+
+
+/// Monotonic clock measuring real time since it was created.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock { start: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> SystemClock {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+
+/// Clock that starts at zero and moves only when advanced or slept on. Clones share the same
+/// time, so test can keep one and give another to the loop.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Rc<Cell<Duration>>,
+}
+
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        ManualClock::default()
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+
+    /// Move clock to given time. Panics if that would move it backwards.
+    pub fn set(&self, now: Duration) {
+        assert!(now >= self.now.get(), "clock can't go backwards");
+        self.now.set(now);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, ManualClock, SystemClock};
+    use std::time::Duration;
+
+    #[test]
+    fn clock() {
+        let clock = ManualClock::new();
+        let shared = clock.clone();
+        assert!(clock.now() == Duration::new(0, 0));
+        shared.advance(Duration::from_millis(16));
+        shared.advance(Duration::from_millis(16));
+        assert!(clock.now() == Duration::from_millis(32));
+        clock.set(Duration::from_secs(1));
+        assert!(shared.now() == Duration::from_secs(1));
+        clock.sleep(Duration::from_secs(1));
+        assert!(shared.now() == Duration::from_secs(2));
+
+        let system = SystemClock::new();
+        let before = system.now();
+        assert!(system.now() >= before);
+    }
+}
@@ -0,0 +1,184 @@
+//! Running a game without rendering.
+//!
+//! `Headless` updates the game back to back without waiting for real time to pass, which is
+//! what servers and automated tests want. It runs either for an exact number of ticks or until
+//! a condition holds and hands the game back together with statistics of the run.
+//!
+//! Ticks are frames of a `GameLoop` whose `ManualClock` moves exactly one timestep between
+//! them, so the game is updated the same way as by the real loop, just never rendered.
+
+use std::time::Duration;
+
+use game_loop::{check_timestep, Game, GameLoop, LoopStats, MS_PER_UPDATE, Strategy};
+use game_loop::clock::{Clock, ManualClock, SystemClock};
+
+
+/// Result of a headless run.
+#[derive(Debug)]
+pub struct Run<G> {
+    pub game: G,
+    /// `elapsed` is the real time run took, `frames` and `updates` both count ticks.
+    pub stats: LoopStats,
+    /// Whether run reached its goal rather than stopping because game quit or `max_ticks` ran
+    /// out.
+    pub completed: bool,
+}
+
+pub struct Headless<C: Clock = SystemClock> {
+    /// Most ticks `Headless::until` runs, in case condition never holds.
+    pub max_ticks: u64,
+    /// Game time every tick simulates.
+    timestep: Duration,
+    /// Measures real time of the run.
+    clock: C,
+}
+
+impl Headless {
+    pub fn new() -> Headless {
+        Headless::with_clock(SystemClock::new())
+    }
+}
+
+impl Default for Headless {
+    fn default() -> Headless {
+        Headless::new()
+    }
+}
+
+impl<C: Clock> Headless<C> {
+    pub fn with_clock(clock: C) -> Headless<C> {
+        Headless {
+            timestep: Duration::from_millis(MS_PER_UPDATE),
+            max_ticks: u64::max_value(),
+            clock: clock,
+        }
+    }
+
+    pub fn timestep(&self) -> Duration {
+        self.timestep
+    }
+
+    /// Panics if `timestep` is zero, same as `GameLoop::set_timestep`.
+    pub fn set_timestep(&mut self, timestep: Duration) {
+        check_timestep(timestep);
+        self.timestep = timestep;
+    }
+
+    /// Update game exactly `ticks` times.
+    pub fn ticks<G: Game>(&self, game: G, ticks: u64) -> Run<G> {
+        self.run(game, ticks, |_, tick| tick == ticks)
+    }
+
+    /// Update game until `done` returns true. It's checked before the first tick as well.
+    pub fn until<G, F>(&self, game: G, mut done: F) -> Run<G>
+        where G: Game,
+              F: FnMut(&G) -> bool
+    {
+        self.run(game, self.max_ticks, |game, _| done(game))
+    }
+
+    fn run<G, F>(&self, mut game: G, limit: u64, mut done: F) -> Run<G>
+        where G: Game,
+              F: FnMut(&G, u64) -> bool
+    {
+        let start = self.clock.now();
+        let ticks = ManualClock::new();
+        let mut game_loop = GameLoop::with_clock(ticks.clone());
+        game_loop.strategy = Strategy::FixedInterpolated;
+        game_loop.set_timestep(self.timestep);
+
+        let mut completed = false;
+        loop {
+            let updates = game_loop.stats().updates;
+            if done(&game, updates) {
+                completed = true;
+                break;
+            }
+            if updates == limit || game.should_quit() {
+                break;
+            }
+            ticks.advance(self.timestep);
+            game_loop.frame(&mut Unrendered(&mut game));
+        }
+
+        let mut stats: LoopStats = *game_loop.stats();
+        stats.elapsed = self.clock.now() - start;
+        Run {
+            game: game,
+            stats: stats,
+            completed: completed,
+        }
+    }
+}
+
+
+/// Game that isn't rendered.
+struct Unrendered<'a, G: 'a>(&'a mut G);
+
+impl<'a, G: Game> Game for Unrendered<'a, G> {
+    fn process_input(&mut self) {
+        self.0.process_input();
+    }
+    fn update(&mut self, dt: Duration) {
+        self.0.update(dt);
+    }
+    fn render(&mut self, _alpha: f32) {}
+    fn should_quit(&self) -> bool {
+        self.0.should_quit()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Headless;
+    use component::World;
+    use game_loop::Game;
+    use game_loop::clock::ManualClock;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Countdown {
+        left: u32,
+        rendered: bool,
+    }
+
+    impl Game for Countdown {
+        fn process_input(&mut self) {}
+        fn update(&mut self, _dt: Duration) {
+            self.left -= 1;
+        }
+        fn render(&mut self, _alpha: f32) {
+            self.rendered = true;
+        }
+        fn should_quit(&self) -> bool {
+            self.left == 0
+        }
+    }
+
+    #[test]
+    fn headless() {
+        let mut headless = Headless::with_clock(ManualClock::new());
+        headless.set_timestep(Duration::from_millis(10));
+
+        let mut world = World::new();
+        let moving = world.create_box(0.0, 0.0);
+        let run = headless.ticks(world, 10);
+        assert!(run.completed);
+        assert!(run.stats.updates == 10);
+        assert!(run.stats.simulated == Duration::from_millis(100));
+        assert!(run.game.displacements[moving].x() == 10.0);
+
+        let run = headless.until(run.game, |world| world.displacements[moving].x() >= 15.0);
+        assert!(run.completed && run.stats.updates == 5);
+
+        // Game quitting stops the run early.
+        let run = headless.ticks(Countdown { left: 3, rendered: false }, 5);
+        assert!(!run.completed && run.stats.updates == 3);
+        assert!(!run.game.rendered);
+
+        headless.max_ticks = 100;
+        let run = headless.until(Countdown { left: 1000, rendered: false }, |_| false);
+        assert!(!run.completed && run.game.left == 900);
+    }
+}
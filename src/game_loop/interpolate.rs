@@ -0,0 +1,95 @@
+//! Render interpolation.
+//!
+//! With fixed updates, rendering usually happens somewhere between two updates. Keeping state
+//! from the last two updates in a `Snapshot` lets renderer draw it blended by `alpha` given to
+//! `Game::render`, so movement looks smooth at any refresh rate.
+
+use std::mem;
+
+
+pub trait Interpolate {
+    /// Value `alpha` of the way from `self` to `other`, 0 gives `self` and 1 gives `other`.
+    fn interpolate(&self, other: &Self, alpha: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(&self, other: &f32, alpha: f32) -> f32 {
+        self + (other - self) * alpha
+    }
+}
+
+/// Interpolates elements pairwise. Elements without a pair keep the newer value.
+impl<T: Interpolate + Clone> Interpolate for Vec<T> {
+    fn interpolate(&self, other: &Vec<T>, alpha: f32) -> Vec<T> {
+        other.iter()
+            .enumerate()
+            .map(|(i, to)| match self.get(i) {
+                Some(from) => from.interpolate(to, alpha),
+                None => to.clone(),
+            })
+            .collect()
+    }
+}
+
+
+/// State after the previous and the current update.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot<T> {
+    previous: T,
+    current: T,
+}
+
+impl<T: Interpolate + Clone> Snapshot<T> {
+    pub fn new(state: T) -> Snapshot<T> {
+        Snapshot {
+            previous: state.clone(),
+            current: state,
+        }
+    }
+
+    /// Record state after an update.
+    pub fn push(&mut self, state: T) {
+        self.previous = mem::replace(&mut self.current, state);
+    }
+
+    pub fn previous(&self) -> &T {
+        &self.previous
+    }
+
+    pub fn current(&self) -> &T {
+        &self.current
+    }
+
+    /// State to render `alpha` of the way from the previous update to the current one.
+    pub fn blend(&self, alpha: f32) -> T {
+        self.previous.interpolate(&self.current, alpha)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Interpolate, Snapshot};
+    use component::{Displacement, Velocity};
+
+    #[test]
+    fn interpolate() {
+        assert!(2.0.interpolate(&4.0, 0.25) == 2.5);
+
+        let mut snapshot = Snapshot::new(Displacement::new(0.0, 10.0));
+        snapshot.push(Displacement::new(4.0, 20.0));
+        let blended = snapshot.blend(0.5);
+        assert!(blended.x() == 2.0 && blended.y() == 15.0);
+        assert!(snapshot.blend(0.0).x() == snapshot.previous().x());
+        assert!(snapshot.blend(1.0).y() == snapshot.current().y());
+
+        let slow = Velocity::new(1.0, 0.0).interpolate(&Velocity::new(3.0, -2.0), 0.5);
+        assert!(slow.x() == 2.0 && slow.y() == -1.0);
+
+        // Entity spawned after the previous update just appears where it is.
+        let before = vec![Displacement::new(0.0, 0.0)];
+        let after = vec![Displacement::new(2.0, 2.0), Displacement::new(7.0, 7.0)];
+        let blended = before.interpolate(&after, 0.5);
+        assert!(blended[0].x() == 1.0 && blended[1].x() == 7.0);
+    }
+}
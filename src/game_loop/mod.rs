@@ -0,0 +1,447 @@
+//! Game Loop Pattern
+//! http://gameprogrammingpatterns.com/game-loop.html
+
+pub mod clock;
+pub mod headless;
+pub mod interpolate;
+pub mod profiler;
+
+use std::time::Duration;
+
+use self::clock::{Clock, SystemClock};
+use self::profiler::{FrameTiming, NoProfiler, Profiler};
+
+/// Default length of a single update.
+pub const MS_PER_UPDATE: u64 = 16;
+/// Default limit of updates in a single frame.
+static MAX_UPDATES_PER_FRAME: u32 = 5;
+
+
+/// Game driven by the `GameLoop`.
+pub trait Game {
+    fn process_input(&mut self);
+    /// Advance game by `dt`. It's the same every time unless the loop uses `Strategy::Variable`
+    /// or `Strategy::SemiFixed`.
+    fn update(&mut self, dt: Duration);
+    /// Draw the game. With `Strategy::FixedInterpolated` `alpha` is how far between the last and
+    /// the next update we are, from 0 to 1, so rendering can interpolate. Otherwise it's 0.
+    fn render(&mut self, alpha: f32);
+    /// Checked before every frame, `GameLoop::run` stops once it returns true.
+    fn should_quit(&self) -> bool {
+        false
+    }
+}
+
+
+/// How the loop divides time between updates and rendering.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// Single update per frame with however much time has passed. Game behaves differently
+    /// depending on framerate.
+    Variable,
+    /// Single update of `timestep` per frame, then sleep for the rest of it. Game slows down
+    /// when a frame takes longer than `timestep`.
+    FixedCapped,
+    /// Update with all the time that has passed, split into steps no longer than `timestep`.
+    SemiFixed,
+    /// Fixed updates of `timestep` catching up with real time and rendering as often as
+    /// possible in between.
+    FixedInterpolated,
+}
+
+/// Statistics every strategy keeps, so they can be compared.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LoopStats {
+    pub frames: u64,
+    pub updates: u64,
+    /// Real time measured by the loop's clock.
+    pub elapsed: Duration,
+    /// Game time passed to updates.
+    pub simulated: Duration,
+    /// Time spent sleeping to cap the framerate.
+    pub slept: Duration,
+    /// Time never simulated because the loop couldn't keep up.
+    pub dropped: Duration,
+}
+
+
+/// Game loop driver with a selectable `Strategy`.
+///
+/// All time is read from the `Clock`, so with a `ManualClock` the loop makes exactly the same
+/// updates every time it's given the same elapsed times. Timing of every frame goes to the
+/// `Profiler`.
+pub struct GameLoop<C: Clock = SystemClock, P: Profiler = NoProfiler> {
+    pub strategy: Strategy,
+    /// Most updates done in a single frame. When machine can't keep up, time it couldn't catch
+    /// up with is dropped instead of making every next frame even longer.
+    pub max_updates: u32,
+    pub profiler: P,
+    /// Game time that passes with every fixed update, or the longest one for
+    /// `Strategy::SemiFixed`.
+    timestep: Duration,
+    clock: C,
+    previous: Duration,
+    lag: Duration,
+    stats: LoopStats,
+}
+
+impl GameLoop {
+    pub fn new() -> GameLoop {
+        GameLoop::with_clock(SystemClock::new())
+    }
+}
+
+impl Default for GameLoop {
+    fn default() -> GameLoop {
+        GameLoop::new()
+    }
+}
+
+impl<C: Clock> GameLoop<C> {
+    pub fn with_clock(clock: C) -> GameLoop<C> {
+        GameLoop::with_profiler(clock, NoProfiler)
+    }
+}
+
+impl<C: Clock, P: Profiler> GameLoop<C, P> {
+    pub fn with_profiler(clock: C, profiler: P) -> GameLoop<C, P> {
+        GameLoop {
+            strategy: Strategy::FixedInterpolated,
+            timestep: Duration::from_millis(MS_PER_UPDATE),
+            max_updates: MAX_UPDATES_PER_FRAME,
+            profiler: profiler,
+            previous: clock.now(),
+            clock: clock,
+            lag: Duration::new(0, 0),
+            stats: LoopStats::default(),
+        }
+    }
+
+    pub fn timestep(&self) -> Duration {
+        self.timestep
+    }
+
+    /// Panics if `timestep` is zero, as no amount of updates would ever catch up with time.
+    pub fn set_timestep(&mut self, timestep: Duration) {
+        check_timestep(timestep);
+        self.timestep = timestep;
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Time that has passed but wasn't simulated yet by `Strategy::FixedInterpolated`.
+    pub fn lag(&self) -> Duration {
+        self.lag
+    }
+
+    pub fn stats(&self) -> &LoopStats {
+        &self.stats
+    }
+
+    /// Run frames until the game wants to quit.
+    pub fn run<G: Game>(&mut self, game: &mut G) {
+        while !game.should_quit() {
+            self.frame(game);
+        }
+    }
+
+    /// Run single iteration of the loop and return number of updates it made. Time is measured
+    /// from the previous frame or, for the first one, from creation of the loop.
+    pub fn frame<G: Game>(&mut self, game: &mut G) -> u32 {
+        let start = self.clock.now();
+        let elapsed = start - self.previous;
+        self.previous = start;
+        self.stats.frames += 1;
+        self.stats.elapsed += elapsed;
+        let profiling = self.profiler.enabled();
+        let dropped = self.stats.dropped;
+        let mut timing = FrameTiming::default();
+        let mut mark = start;
+
+        game.process_input();
+        if profiling {
+            timing.input = self.lap(&mut mark);
+        }
+        let updates = match self.strategy {
+            Strategy::Variable => {
+                self.update(game, elapsed);
+                1
+            }
+            Strategy::FixedCapped => {
+                let timestep = self.timestep;
+                self.update(game, timestep);
+                1
+            }
+            Strategy::SemiFixed => self.semi_fixed(game, elapsed),
+            Strategy::FixedInterpolated => self.fixed_interpolated(game, elapsed),
+        };
+        if profiling {
+            timing.update = self.lap(&mut mark);
+        }
+        if self.strategy == Strategy::FixedInterpolated {
+            game.render(alpha(self.lag, self.timestep));
+        } else {
+            game.render(0.0);
+        }
+        if profiling {
+            timing.render = self.lap(&mut mark);
+            timing.frame_time = elapsed;
+            timing.updates = updates;
+            timing.lag = self.lag;
+            // Partially dropped step of `Strategy::SemiFixed` still counts as an update.
+            let step = nanos(self.timestep);
+            timing.dropped_updates = ((nanos(self.stats.dropped - dropped) + step - 1) /
+                                      step) as u32;
+            self.profiler.record(&timing);
+        }
+
+        if self.strategy == Strategy::FixedCapped {
+            let spent = self.clock.now() - start;
+            if spent < self.timestep {
+                self.clock.sleep(self.timestep - spent);
+                self.stats.slept += self.timestep - spent;
+            }
+        }
+        updates
+    }
+
+    /// Time since `mark`, which moves to now.
+    fn lap(&self, mark: &mut Duration) -> Duration {
+        let now = self.clock.now();
+        let lap = now - *mark;
+        *mark = now;
+        lap
+    }
+
+    fn update<G: Game>(&mut self, game: &mut G, dt: Duration) {
+        game.update(dt);
+        self.stats.updates += 1;
+        self.stats.simulated += dt;
+    }
+
+    fn semi_fixed<G: Game>(&mut self, game: &mut G, elapsed: Duration) -> u32 {
+        let mut left = elapsed;
+        let mut updates = 0;
+        while left > Duration::new(0, 0) {
+            if updates == self.max_updates {
+                self.stats.dropped += left;
+                break;
+            }
+            let dt = if left < self.timestep { left } else { self.timestep };
+            self.update(game, dt);
+            left -= dt;
+            updates += 1;
+        }
+        updates
+    }
+
+    fn fixed_interpolated<G: Game>(&mut self, game: &mut G, elapsed: Duration) -> u32 {
+        self.lag += elapsed;
+        let mut updates = 0;
+        while self.lag >= self.timestep {
+            if updates == self.max_updates {
+                let lag = from_nanos(nanos(self.lag) % nanos(self.timestep));
+                self.stats.dropped += self.lag - lag;
+                self.lag = lag;
+                break;
+            }
+            let timestep = self.timestep;
+            self.update(game, timestep);
+            self.lag -= self.timestep;
+            updates += 1;
+        }
+        updates
+    }
+}
+
+/// How far into the next update `lag` is, which is what `Game::render` gets as `alpha` with
+/// `Strategy::FixedInterpolated`.
+pub fn alpha(lag: Duration, timestep: Duration) -> f32 {
+    nanos(lag) as f32 / nanos(timestep) as f32
+}
+
+/// Panic on timestep no loop can run with.
+fn check_timestep(timestep: Duration) {
+    assert!(timestep > Duration::new(0, 0), "game loop timestep must not be zero");
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
+
+fn from_nanos(nanos: u64) -> Duration {
+    Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Game, GameLoop, LoopStats, Strategy};
+    use super::clock::{Clock, ManualClock};
+    use super::profiler::{FrameStats, Phase};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Counter {
+        inputs: u32,
+        updates: u32,
+        steps: Vec<Duration>,
+        alphas: Vec<f32>,
+    }
+
+    impl Game for Counter {
+        fn process_input(&mut self) {
+            self.inputs += 1;
+        }
+        fn update(&mut self, dt: Duration) {
+            self.updates += 1;
+            self.steps.push(dt);
+        }
+        fn render(&mut self, alpha: f32) {
+            self.alphas.push(alpha);
+        }
+        fn should_quit(&self) -> bool {
+            self.inputs == 4
+        }
+    }
+
+    #[test]
+    fn game_loop() {
+        let clock = ManualClock::new();
+        let mut game_loop = GameLoop::with_clock(clock.clone());
+        game_loop.set_timestep(Duration::from_millis(10));
+        game_loop.max_updates = 3;
+        let mut game = Counter::default();
+
+        assert!(game_loop.frame(&mut game) == 0);
+        clock.advance(Duration::from_millis(25));
+        assert!(game_loop.frame(&mut game) == 2);
+        assert!(game.alphas == vec![0.0, 0.5]);
+
+        // Long stall only catches up three updates and drops the rest.
+        clock.advance(Duration::from_millis(977));
+        assert!(game_loop.frame(&mut game) == 3);
+        assert!(game_loop.lag() == Duration::from_millis(2));
+
+        game_loop.run(&mut game);
+        assert!(game.inputs == 4);
+        assert!(game.updates == 5);
+        assert!(*game_loop.stats() ==
+                LoopStats {
+            frames: 4,
+            updates: 5,
+            elapsed: Duration::from_millis(1002),
+            simulated: Duration::from_millis(50),
+            slept: Duration::new(0, 0),
+            dropped: Duration::from_millis(950),
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "timestep must not be zero")]
+    fn zero_timestep() {
+        GameLoop::with_clock(ManualClock::new()).set_timestep(Duration::new(0, 0));
+    }
+
+    #[test]
+    fn strategies() {
+        let ms = Duration::from_millis;
+        let run = |strategy| {
+            let clock = ManualClock::new();
+            let mut game_loop = GameLoop::with_clock(clock.clone());
+            game_loop.strategy = strategy;
+            game_loop.set_timestep(ms(10));
+            game_loop.max_updates = 2;
+            let mut game = Counter::default();
+            for &frame in &[5, 25, 40] {
+                clock.advance(ms(frame));
+                game_loop.frame(&mut game);
+            }
+            (game.steps, *game_loop.stats(), clock.now())
+        };
+
+        let (steps, stats, _) = run(Strategy::Variable);
+        assert!(steps == vec![ms(5), ms(25), ms(40)]);
+        assert!(stats.simulated == ms(70));
+
+        // Every frame sleeps until the end of its timestep.
+        let (steps, stats, now) = run(Strategy::FixedCapped);
+        assert!(steps == vec![ms(10), ms(10), ms(10)]);
+        assert!(stats.slept == ms(30));
+        assert!(now == ms(100));
+
+        let (steps, stats, _) = run(Strategy::SemiFixed);
+        assert!(steps == vec![ms(5), ms(10), ms(10), ms(10), ms(10)]);
+        assert!(stats.dropped == ms(25));
+
+        let (steps, stats, _) = run(Strategy::FixedInterpolated);
+        assert!(steps == vec![ms(10), ms(10), ms(10), ms(10)]);
+        assert!(stats.dropped == ms(30));
+        assert!(stats.frames == 3 && stats.updates == 4 && stats.elapsed == ms(70));
+    }
+
+    #[test]
+    fn deterministic() {
+        // 60 updates per second don't divide a second into whole milliseconds or nanoseconds.
+        let frames = [16, 17, 16, 33, 1, 0, 50, 17];
+        let run = || {
+            let clock = ManualClock::new();
+            let mut game_loop = GameLoop::with_clock(clock.clone());
+            game_loop.set_timestep(Duration::new(0, 1_000_000_000 / 60));
+            let mut game = Counter::default();
+            let updates: Vec<u32> = frames.iter()
+                .map(|&ms| {
+                    clock.advance(Duration::from_millis(ms));
+                    game_loop.frame(&mut game)
+                })
+                .collect();
+            (updates, game.alphas)
+        };
+        let (updates, alphas) = run();
+        // 150ms is just a hair over nine updates, rounding errors would lose the last one.
+        assert!(updates == vec![0, 1, 1, 2, 0, 0, 3, 2]);
+        assert!((updates, alphas) == run());
+    }
+
+    /// Game that takes a while to update and render.
+    struct Slow {
+        clock: ManualClock,
+    }
+
+    impl Game for Slow {
+        fn process_input(&mut self) {}
+        fn update(&mut self, _dt: Duration) {
+            self.clock.advance(Duration::from_millis(2));
+        }
+        fn render(&mut self, _alpha: f32) {
+            self.clock.advance(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn profiler() {
+        let ms = Duration::from_millis;
+        let clock = ManualClock::new();
+        let mut game_loop = GameLoop::with_profiler(clock.clone(), FrameStats::new(8));
+        game_loop.set_timestep(ms(10));
+        game_loop.max_updates = 2;
+        let mut game = Slow { clock: clock.clone() };
+        for _ in 0..4 {
+            clock.advance(ms(25));
+            game_loop.frame(&mut game);
+        }
+
+        let stats = &game_loop.profiler;
+        assert!(stats.frames().len() == 4);
+        assert!(stats.frames()[0].frame_time == ms(25));
+        // Every frame after the first also includes the previous frame's update and render.
+        assert!(stats.frames()[1].frame_time == ms(34));
+        assert!(stats.frames()[1].updates == 2);
+        assert!(stats.phase(Phase::Render) == ms(5));
+        assert!(stats.frames()[2].update == ms(4));
+        assert!(stats.frames()[1].dropped_updates == 1);
+        assert!(stats.fps() > 0.0);
+    }
+}
@@ -0,0 +1,263 @@
+//! Frame timing for the game loop.
+//!
+//! `GameLoop` hands timing of every frame to its `Profiler`. `NoProfiler` is the default and
+//! turns measuring off completely, `FrameStats` keeps a rolling window of frames and can export
+//! reports of them.
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+
+/// Part of a frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Input,
+    Update,
+    Render,
+}
+
+/// Timing of a single frame.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FrameTiming {
+    /// Time since the previous frame started.
+    pub frame_time: Duration,
+    pub input: Duration,
+    /// All updates of the frame together.
+    pub update: Duration,
+    pub render: Duration,
+    pub updates: u32,
+    /// Time left to simulate after the frame.
+    pub lag: Duration,
+    /// Updates skipped because the loop couldn't keep up.
+    pub dropped_updates: u32,
+}
+
+impl FrameTiming {
+    pub fn phase(&self, phase: Phase) -> Duration {
+        match phase {
+            Phase::Input => self.input,
+            Phase::Update => self.update,
+            Phase::Render => self.render,
+        }
+    }
+}
+
+
+pub trait Profiler {
+    /// Loop doesn't even read the clock to time phases when this is false.
+    fn enabled(&self) -> bool {
+        true
+    }
+    fn record(&mut self, frame: &FrameTiming);
+}
+
+/// Profiler that measures nothing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProfiler;
+
+impl Profiler for NoProfiler {
+    fn enabled(&self) -> bool {
+        false
+    }
+    fn record(&mut self, _frame: &FrameTiming) {}
+}
+
+
+/// Summary of the frames in the window of `FrameStats`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Report {
+    pub fps: f32,
+    pub ups: f32,
+    pub min: Duration,
+    pub average: Duration,
+    pub max: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub input: Duration,
+    pub update: Duration,
+    pub render: Duration,
+    /// Updates dropped since the loop started.
+    pub dropped_updates: u64,
+}
+
+/// Profiler keeping statistics of last `window` frames.
+pub struct FrameStats {
+    frames: VecDeque<FrameTiming>,
+    window: usize,
+    dropped_updates: u64,
+    frame_count: u64,
+    report_every: u64,
+    exporter: Option<Box<FnMut(&Report)>>,
+}
+
+impl FrameStats {
+    /// Window of zero frames is taken as one, as there'd be nothing to make statistics of.
+    pub fn new(window: usize) -> FrameStats {
+        let window = cmp::max(window, 1);
+        FrameStats {
+            frames: VecDeque::with_capacity(window),
+            window: window,
+            dropped_updates: 0,
+            frame_count: 0,
+            report_every: 0,
+            exporter: None,
+        }
+    }
+
+    /// Call `exporter` with a report every `frames` frames, for example to send it to telemetry.
+    pub fn on_report<F>(&mut self, frames: u64, exporter: F)
+        where F: FnMut(&Report) + 'static
+    {
+        self.report_every = frames;
+        self.exporter = Some(Box::new(exporter));
+    }
+
+    /// Frames in the window, oldest first.
+    pub fn frames(&self) -> &VecDeque<FrameTiming> {
+        &self.frames
+    }
+
+    pub fn fps(&self) -> f32 {
+        per_second(self.frames.len() as u64, self.total(|f| f.frame_time))
+    }
+
+    pub fn ups(&self) -> f32 {
+        let updates = self.frames.iter().map(|f| f.updates as u64).sum();
+        per_second(updates, self.total(|f| f.frame_time))
+    }
+
+    pub fn min(&self) -> Duration {
+        self.frames.iter().map(|f| f.frame_time).min().unwrap_or_default()
+    }
+
+    pub fn max(&self) -> Duration {
+        self.frames.iter().map(|f| f.frame_time).max().unwrap_or_default()
+    }
+
+    pub fn average(&self) -> Duration {
+        self.average_of(|f| f.frame_time)
+    }
+
+    /// Frame time that `percent` of frames in the window don't exceed.
+    pub fn percentile(&self, percent: f32) -> Duration {
+        let mut times: Vec<Duration> = self.frames.iter().map(|f| f.frame_time).collect();
+        if times.is_empty() {
+            return Duration::default();
+        }
+        times.sort();
+        let rank = (percent / 100.0 * times.len() as f32).ceil() as usize;
+        times[rank.max(1).min(times.len()) - 1]
+    }
+
+    /// Average time spent in phase.
+    pub fn phase(&self, phase: Phase) -> Duration {
+        self.average_of(|f| f.phase(phase))
+    }
+
+    /// Updates dropped since the loop started, not just in the window.
+    pub fn dropped_updates(&self) -> u64 {
+        self.dropped_updates
+    }
+
+    pub fn report(&self) -> Report {
+        Report {
+            fps: self.fps(),
+            ups: self.ups(),
+            min: self.min(),
+            average: self.average(),
+            max: self.max(),
+            p95: self.percentile(95.0),
+            p99: self.percentile(99.0),
+            input: self.phase(Phase::Input),
+            update: self.phase(Phase::Update),
+            render: self.phase(Phase::Render),
+            dropped_updates: self.dropped_updates,
+        }
+    }
+
+    fn total<F: Fn(&FrameTiming) -> Duration>(&self, time: F) -> Duration {
+        self.frames.iter().map(time).fold(Duration::default(), |a, b| a + b)
+    }
+
+    fn average_of<F: Fn(&FrameTiming) -> Duration>(&self, time: F) -> Duration {
+        if self.frames.is_empty() {
+            Duration::default()
+        } else {
+            self.total(time) / self.frames.len() as u32
+        }
+    }
+}
+
+impl Profiler for FrameStats {
+    fn record(&mut self, frame: &FrameTiming) {
+        if self.frames.len() >= self.window {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(*frame);
+        self.dropped_updates += frame.dropped_updates as u64;
+        self.frame_count += 1;
+
+        if self.report_every > 0 && self.frame_count % self.report_every == 0 {
+            let report = self.report();
+            if let Some(ref mut exporter) = self.exporter {
+                exporter(&report);
+            }
+        }
+    }
+}
+
+fn per_second(count: u64, time: Duration) -> f32 {
+    let seconds = time.as_secs() as f32 + time.subsec_nanos() as f32 / 1e9;
+    if seconds > 0.0 {
+        count as f32 / seconds
+    } else {
+        0.0
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameStats, FrameTiming, Phase, Profiler, Report};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    #[test]
+    fn frame_stats() {
+        let ms = Duration::from_millis;
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let exported = reports.clone();
+        let mut stats = FrameStats::new(4);
+        stats.on_report(2, move |report: &Report| exported.borrow_mut().push(*report));
+
+        for &(time, updates, dropped) in &[(100, 1, 0), (10, 1, 0), (20, 2, 0), (30, 3, 1),
+                                           (40, 2, 2)] {
+            stats.record(&FrameTiming {
+                frame_time: ms(time),
+                render: ms(time / 10),
+                updates: updates,
+                dropped_updates: dropped,
+                ..FrameTiming::default()
+            });
+        }
+
+        // Oldest frame already fell out of the window.
+        assert!(stats.min() == ms(10) && stats.max() == ms(40));
+        assert!(stats.average() == ms(25));
+        assert!(stats.percentile(50.0) == ms(20));
+        assert!(stats.percentile(95.0) == ms(40));
+        assert!(stats.fps() == 40.0);
+        assert!(stats.ups() == 80.0);
+        assert!(stats.phase(Phase::Render) == Duration::new(0, 2_500_000));
+        assert!(stats.dropped_updates() == 3);
+        assert!(reports.borrow().len() == 2);
+        assert!(reports.borrow()[1].dropped_updates == 1);
+
+        let mut latest = FrameStats::new(0);
+        latest.record(&FrameTiming::default());
+        latest.record(&FrameTiming::default());
+        assert!(latest.frames().len() == 1);
+    }
+}
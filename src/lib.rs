@@ -14,6 +14,7 @@ pub mod observer;
 pub mod prototype;
 pub mod state;
 pub mod double_buffer;
+pub mod game_loop;
 pub mod bytecode;
 pub mod type_object;
 pub mod component;
@@ -0,0 +1,475 @@
+//! Data modeling with prototypes.
+//!
+//! Data is a JSON-like object of named entries. Entry can name another one as its `prototype`
+//! and gets all of its fields, except the ones it sets itself:
+//!
+//! ```text
+//! # Everything after `#` is a comment.
+//! {
+//!     "goblin grunt": { "kind": "ghost", "health": 20, "speed": 3 },
+//!     "goblin wizard": { "prototype": "goblin grunt", "spells": ["fire ball"] },
+//!     "goblin archer": { "prototype": "goblin grunt", "speed": 5 }
+//! }
+//! ```
+//!
+//! `load_monsters` turns entries into monsters, picking the type by `kind` field.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use prototype::{Demon, Ghost, Monster, PrototypeRegistry};
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+}
+
+impl Value {
+    pub fn as_number(&self) -> Option<f64> {
+        match *self {
+            Value::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataErrorKind {
+    UnexpectedChar(char),
+    UnexpectedEnd,
+    /// Unknown word or malformed number.
+    InvalidValue(String),
+    InvalidEscape(char),
+    DuplicateKey(String),
+    /// Data or an entry in it isn't an object.
+    ExpectedObject,
+    UnknownPrototype(String),
+    /// Entries inheriting from each other in a loop, in the order they do.
+    Cycle(Vec<String>),
+    MissingField(String),
+    /// Field has value of the wrong type or out of range.
+    InvalidField(String),
+    UnknownKind(String),
+}
+
+/// Error in data with the line (counting from 1) on which it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataError {
+    pub line: usize,
+    pub kind: DataErrorKind,
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match self.kind {
+            DataErrorKind::UnexpectedChar(c) => write!(f, "unexpected `{}`", c),
+            DataErrorKind::UnexpectedEnd => write!(f, "unexpected end of data"),
+            DataErrorKind::InvalidValue(ref v) => write!(f, "invalid value `{}`", v),
+            DataErrorKind::InvalidEscape(c) => write!(f, "invalid escape `\\{}`", c),
+            DataErrorKind::DuplicateKey(ref k) => write!(f, "`{}` is there twice", k),
+            DataErrorKind::ExpectedObject => write!(f, "expected an object"),
+            DataErrorKind::UnknownPrototype(ref p) => write!(f, "unknown prototype `{}`", p),
+            DataErrorKind::Cycle(ref chain) => {
+                write!(f, "prototypes form a cycle: {} -> {}", chain.join(" -> "), chain[0])
+            }
+            DataErrorKind::MissingField(ref field) => write!(f, "missing `{}`", field),
+            DataErrorKind::InvalidField(ref field) => write!(f, "invalid `{}`", field),
+            DataErrorKind::UnknownKind(ref kind) => write!(f, "unknown kind `{}`", kind),
+        }
+    }
+}
+
+
+/// Entry with fields inherited from its prototypes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Line the entry starts on.
+    pub line: usize,
+    pub prototype: Option<String>,
+    pub fields: BTreeMap<String, Value>,
+}
+
+impl Entry {
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        self.fields.get(field)
+    }
+
+    /// Monster of type named by `kind`, with `health` and `speed`.
+    pub fn to_monster(&self) -> Result<Box<Monster>, DataError> {
+        let kind = self.field("kind")?.as_str().ok_or_else(|| self.invalid("kind"))?;
+        let health = self.integer("health")?;
+        let speed = self.integer("speed")?;
+        if health < i32::min_value() as i64 || health > i32::max_value() as i64 {
+            return Err(self.invalid("health"));
+        }
+        if speed < 0 || speed > u32::max_value() as i64 {
+            return Err(self.invalid("speed"));
+        }
+        match kind {
+            "ghost" => Ok(Box::new(Ghost::new(health as i32, speed as u32))),
+            "demon" => Ok(Box::new(Demon::new(health as i32, speed as u32))),
+            _ => {
+                Err(DataError {
+                    line: self.line,
+                    kind: DataErrorKind::UnknownKind(kind.to_owned()),
+                })
+            }
+        }
+    }
+
+    fn field(&self, field: &str) -> Result<&Value, DataError> {
+        self.fields.get(field).ok_or_else(|| {
+            DataError {
+                line: self.line,
+                kind: DataErrorKind::MissingField(field.to_owned()),
+            }
+        })
+    }
+
+    fn integer(&self, field: &str) -> Result<i64, DataError> {
+        match self.field(field)?.as_number() {
+            Some(n) if n.fract() == 0.0 => Ok(n as i64),
+            _ => Err(self.invalid(field)),
+        }
+    }
+
+    fn invalid(&self, field: &str) -> DataError {
+        DataError {
+            line: self.line,
+            kind: DataErrorKind::InvalidField(field.to_owned()),
+        }
+    }
+}
+
+
+/// Read entries and resolve their prototypes.
+pub fn load(text: &str) -> Result<BTreeMap<String, Entry>, DataError> {
+    let mut parser = Parser {
+        chars: text.chars().peekable(),
+        line: 1,
+    };
+    if parser.peek() != Some('{') {
+        return Err(parser.error(DataErrorKind::ExpectedObject));
+    }
+    let raw = parser.object(|p| {
+            if p.peek() != Some('{') {
+                return Err(p.error(DataErrorKind::ExpectedObject));
+            }
+            p.object(Parser::value)
+        })?;
+    if let Some(c) = parser.peek() {
+        return Err(parser.error(DataErrorKind::UnexpectedChar(c)));
+    }
+
+    let mut entries = BTreeMap::new();
+    for name in raw.keys() {
+        resolve(&raw, name, &mut Vec::new(), &mut entries)?;
+    }
+    Ok(entries)
+}
+
+/// Load entries into a registry of monsters named after them.
+pub fn load_monsters(text: &str) -> Result<PrototypeRegistry, DataError> {
+    let mut registry = PrototypeRegistry::new();
+    for (name, entry) in load(text)? {
+        registry.register(&name, entry.to_monster()?);
+    }
+    Ok(registry)
+}
+
+
+/// Fields by name with the line they're on.
+type Fields<T> = BTreeMap<String, (usize, T)>;
+
+/// Resolve entry after its prototypes. `chain` holds entries waiting for this one.
+fn resolve(raw: &Fields<Fields<Value>>,
+           name: &str,
+           chain: &mut Vec<String>,
+           entries: &mut BTreeMap<String, Entry>)
+           -> Result<(), DataError> {
+    if entries.contains_key(name) {
+        return Ok(());
+    }
+    let (line, ref fields) = raw[name];
+    if let Some(start) = chain.iter().position(|n| n == name) {
+        return Err(DataError {
+            line: line,
+            kind: DataErrorKind::Cycle(chain[start..].to_vec()),
+        });
+    }
+
+    let mut entry = Entry {
+        line: line,
+        prototype: None,
+        fields: BTreeMap::new(),
+    };
+    if let Some(&(prototype_line, ref prototype)) = fields.get("prototype") {
+        let error = |kind| {
+            DataError {
+                line: prototype_line,
+                kind: kind,
+            }
+        };
+        let prototype = prototype.as_str()
+            .ok_or_else(|| error(DataErrorKind::InvalidField("prototype".to_owned())))?;
+        if !raw.contains_key(prototype) {
+            return Err(error(DataErrorKind::UnknownPrototype(prototype.to_owned())));
+        }
+        chain.push(name.to_owned());
+        resolve(raw, prototype, chain, entries)?;
+        chain.pop();
+        entry.prototype = Some(prototype.to_owned());
+        entry.fields = entries[prototype].fields.clone();
+    }
+    for (field, &(_, ref value)) in fields {
+        if field != "prototype" {
+            entry.fields.insert(field.clone(), value.clone());
+        }
+    }
+    entries.insert(name.to_owned(), entry);
+    Ok(())
+}
+
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, kind: DataErrorKind) -> DataError {
+        DataError {
+            line: self.line,
+            kind: kind,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if c == '#' {
+                while self.chars.peek().map_or(false, |&c| c != '\n') {
+                    self.chars.next();
+                }
+            } else if c.is_whitespace() {
+                if c == '\n' {
+                    self.line += 1;
+                }
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Next character that isn't whitespace or comment.
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.peek().cloned()
+    }
+
+    fn next(&mut self) -> Result<char, DataError> {
+        self.skip_whitespace();
+        self.chars.next().ok_or_else(|| self.error(DataErrorKind::UnexpectedEnd))
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), DataError> {
+        match self.next()? {
+            c if c == expected => Ok(()),
+            c => Err(self.error(DataErrorKind::UnexpectedChar(c))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, DataError> {
+        match self.peek() {
+            Some('{') => {
+                let fields = self.object(Parser::value)?;
+                Ok(Value::Object(fields.into_iter().map(|(k, (_, v))| (k, v)).collect()))
+            }
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Value::String),
+            Some(c) if c.is_alphanumeric() || c == '-' || c == '.' => self.word(),
+            Some(c) => Err(self.error(DataErrorKind::UnexpectedChar(c))),
+            None => Err(self.error(DataErrorKind::UnexpectedEnd)),
+        }
+    }
+
+    /// Object with values read by `value`.
+    fn object<T, F>(&mut self, mut value: F) -> Result<Fields<T>, DataError>
+        where F: FnMut(&mut Parser<'a>) -> Result<T, DataError>
+    {
+        self.expect('{')?;
+        let mut fields = BTreeMap::new();
+        if self.peek() == Some('}') {
+            self.next()?;
+            return Ok(fields);
+        }
+        loop {
+            self.skip_whitespace();
+            let line = self.line;
+            let key = self.string()?;
+            self.expect(':')?;
+            let value = value(self)?;
+            if fields.contains_key(&key) {
+                return Err(DataError {
+                    line: line,
+                    kind: DataErrorKind::DuplicateKey(key),
+                });
+            }
+            fields.insert(key, (line, value));
+            match self.next()? {
+                ',' => {}
+                '}' => return Ok(fields),
+                c => return Err(self.error(DataErrorKind::UnexpectedChar(c))),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, DataError> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        if self.peek() == Some(']') {
+            self.next()?;
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            match self.next()? {
+                ',' => {}
+                ']' => return Ok(Value::Array(values)),
+                c => return Err(self.error(DataErrorKind::UnexpectedChar(c))),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, DataError> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            let c = self.chars.next().ok_or_else(|| self.error(DataErrorKind::UnexpectedEnd))?;
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escaped = self.chars
+                        .next()
+                        .ok_or_else(|| self.error(DataErrorKind::UnexpectedEnd))?;
+                    string.push(match escaped {
+                        '"' => '"',
+                        '\\' => '\\',
+                        'n' => '\n',
+                        't' => '\t',
+                        c => return Err(self.error(DataErrorKind::InvalidEscape(c))),
+                    });
+                }
+                '\n' => return Err(self.error(DataErrorKind::UnexpectedChar('\n'))),
+                c => string.push(c),
+            }
+        }
+    }
+
+    /// Number or `true`/`false`.
+    fn word(&mut self) -> Result<Value, DataError> {
+        let mut word = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_alphanumeric() || c == '-' || c == '+' || c == '.') {
+                break;
+            }
+            word.push(c);
+            self.chars.next();
+        }
+        match &*word {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => {
+                word.parse()
+                    .map(Value::Number)
+                    .map_err(|_| self.error(DataErrorKind::InvalidValue(word.clone())))
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{DataError, DataErrorKind, Value, load, load_monsters};
+
+    const GOBLINS: &'static str = r#"
+# Goblins of all sorts.
+{
+    "goblin grunt": {
+        "kind": "ghost",
+        "health": 20,
+        "speed": 3,
+        "resists": ["cold", "poison"]
+    },
+    "goblin wizard": { "prototype": "goblin grunt", "spells": ["fire ball", "lightning"] },
+    "goblin archer": { "prototype": "goblin grunt", "speed": 5 },
+    "goblin shaman": { "prototype": "goblin wizard", "kind": "demon", "health": 12.0 }
+}
+"#;
+
+    #[test]
+    fn inheritance() {
+        let entries = load(GOBLINS).unwrap();
+        let shaman = &entries["goblin shaman"];
+        assert!(shaman.line == 12);
+        assert!(shaman.prototype == Some("goblin wizard".to_owned()));
+        assert!(shaman.get("speed") == Some(&Value::Number(3.0)));
+        assert!(shaman.get("health") == Some(&Value::Number(12.0)));
+        assert!(shaman.get("resists").is_some() && shaman.get("spells").is_some());
+        assert!(shaman.get("prototype").is_none());
+        assert!(entries["goblin archer"].get("spells").is_none());
+
+        let monsters = load_monsters(GOBLINS).unwrap();
+        assert!(monsters.names() == vec!["goblin archer", "goblin grunt", "goblin shaman",
+                                         "goblin wizard"]);
+        assert!(monsters.spawn("goblin archer").unwrap().speed() == 5);
+        assert!(monsters.spawn("goblin shaman").unwrap().health() == 12);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |line, kind| Err(DataError { line: line, kind: kind });
+
+        let cycle = "{\n\"a\": {\"prototype\": \"b\"},\n\"b\": {\"prototype\": \"c\"},\n\
+                     \"c\": {\"prototype\": \"a\"}\n}";
+        assert!(load(cycle) ==
+                error(2,
+                      DataErrorKind::Cycle(vec!["a".to_owned(), "b".to_owned(), "c".to_owned()])));
+        assert!(load("{\"a\": {\"prototype\": \"a\"}}") ==
+                error(1, DataErrorKind::Cycle(vec!["a".to_owned()])));
+
+        assert!(load("{\n\"a\": {\n\"prototype\": \"ghost\"\n}}") ==
+                error(3, DataErrorKind::UnknownPrototype("ghost".to_owned())));
+        assert!(load("{\"a\": {}, \"a\": {}}") ==
+                error(1, DataErrorKind::DuplicateKey("a".to_owned())));
+        assert!(load("{\"a\": 1}") == error(1, DataErrorKind::ExpectedObject));
+        assert!(load("{\"a\": {\"b\": nope}}") ==
+                error(1, DataErrorKind::InvalidValue("nope".to_owned())));
+        assert!(load("{\n\"a\": {\"b\": [1, 2}}") == error(2, DataErrorKind::UnexpectedChar('}')));
+        assert!(load("{\"a\": {}") == error(1, DataErrorKind::UnexpectedEnd));
+        assert!(load("{} {}") == error(1, DataErrorKind::UnexpectedChar('{')));
+
+        let error = load_monsters("{\"a\": {\"kind\": \"ghost\", \"health\": 1}}").err().unwrap();
+        assert!(error.kind == DataErrorKind::MissingField("speed".to_owned()));
+        assert!(error.to_string() == "line 1: missing `speed`");
+        let error = load_monsters("{\"a\": {\"kind\": \"ghost\", \"health\": 1, \"speed\": -1}}");
+        assert!(error.err().unwrap().kind == DataErrorKind::InvalidField("speed".to_owned()));
+    }
+}
@@ -0,0 +1,232 @@
+//! Prototype Pattern
+//! http://gameprogrammingpatterns.com/prototype.html
+
+pub mod data;
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+pub trait Monster: CloneMonster {
+    fn health(&self) -> i32;
+    fn speed(&self) -> u32;
+    /// Used in template style spawner.
+    /// This one creates new Monster with defaults specified in that method.
+    fn clone_new() -> Self where Self: Sized;
+}
+
+/// Cloning that works through `Box<Monster>`, so spawners can hold monsters of any kind.
+/// Implemented for every `Monster` that is `Clone`.
+pub trait CloneMonster {
+    fn clone_box(&self) -> Box<Monster>;
+}
+
+impl<T: Monster + Clone + 'static> CloneMonster for T {
+    fn clone_box(&self) -> Box<Monster> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<Monster> {
+    fn clone(&self) -> Box<Monster> {
+        self.clone_box()
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Ghost {
+    health: i32,
+    speed: u32,
+}
+
+impl Ghost {
+    pub fn new(health: i32, speed: u32) -> Ghost {
+        Ghost {
+            health: health,
+            speed: speed,
+        }
+    }
+}
+
+impl Monster for Ghost {
+    fn health(&self) -> i32 {
+        self.health
+    }
+
+    fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    fn clone_new() -> Ghost {
+        Ghost {
+            speed: 2,
+            health: 8,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Demon {
+    health: i32,
+    speed: u32,
+}
+
+impl Demon {
+    pub fn new(health: i32, speed: u32) -> Demon {
+        Demon {
+            health: health,
+            speed: speed,
+        }
+    }
+}
+
+impl Monster for Demon {
+    fn health(&self) -> i32 {
+        self.health
+    }
+
+    fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    fn clone_new() -> Demon {
+        Demon {
+            speed: 1,
+            health: 20,
+        }
+    }
+}
+
+
+/// Spawns copies of a concrete monster, or with `Box<Monster>` of any kind.
+pub struct Spawner<T: Clone> {
+    prototype: T,
+}
+
+impl<T: Clone> Spawner<T> {
+    pub fn new(prototype: T) -> Spawner<T> {
+        Spawner { prototype: prototype }
+    }
+    pub fn spawn(&self) -> T {
+        self.prototype.clone()
+    }
+}
+
+
+// Second style of spawner described in Templates section in book.
+pub struct SpawnerT;
+
+impl SpawnerT {
+    pub fn spawn<T: Monster>() -> T {
+        T::clone_new()
+    }
+}
+
+
+/// Spawning with a name that isn't registered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownPrototype(pub String);
+
+impl fmt::Display for UnknownPrototype {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown prototype `{}`", self.0)
+    }
+}
+
+/// Named prototypes, so data like level files can refer to monsters by name. By default they
+/// are `Box<Monster>`, so one registry holds monsters of every kind.
+pub struct PrototypeRegistry<T: Clone = Box<Monster>> {
+    prototypes: BTreeMap<String, T>,
+}
+
+impl<T: Clone> PrototypeRegistry<T> {
+    pub fn new() -> PrototypeRegistry<T> {
+        PrototypeRegistry { prototypes: BTreeMap::new() }
+    }
+
+    /// Add prototype, returning the one it replaced.
+    pub fn register(&mut self, name: &str, prototype: T) -> Option<T> {
+        self.prototypes.insert(name.to_owned(), prototype)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&T> {
+        self.prototypes.get(name)
+    }
+
+    pub fn spawn(&self, name: &str) -> Result<T, UnknownPrototype> {
+        self.prototypes.get(name).cloned().ok_or_else(|| UnknownPrototype(name.to_owned()))
+    }
+
+    /// Registered names in alphabetical order.
+    pub fn names(&self) -> Vec<&str> {
+        self.prototypes.keys().map(|name| &**name).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.prototypes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prototypes.is_empty()
+    }
+}
+
+impl<T: Clone> Default for PrototypeRegistry<T> {
+    fn default() -> PrototypeRegistry<T> {
+        PrototypeRegistry::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Demon, Ghost, PrototypeRegistry, Spawner, SpawnerT, Monster, UnknownPrototype};
+
+    #[test]
+    pub fn prototype() {
+        let ghost_prototype = Ghost::new(15, 3);
+        let ghost_spawner = Spawner::new(ghost_prototype);
+        let ghost = ghost_spawner.spawn();
+        assert!(ghost.health() == 15);
+        assert!(ghost.speed() == 3);
+
+        // Using generic spawner.
+        let ghost2 = SpawnerT::spawn::<Ghost>();
+        assert!(ghost2.health() == 8);
+        assert!(ghost2.speed() == 2);
+        assert!(SpawnerT::spawn::<Demon>().health() == 20);
+    }
+
+    #[test]
+    pub fn mixed_prototypes() {
+        let spawners: Vec<Spawner<Box<Monster>>> = vec![Spawner::new(Box::new(Ghost::new(15, 3))),
+                                                         Spawner::new(Box::new(Demon::new(30, 2)))];
+        let monsters: Vec<Box<Monster>> = spawners.iter().map(|s| s.spawn()).collect();
+        assert!(monsters[0].health() == 15 && monsters[1].health() == 30);
+    }
+
+    #[test]
+    pub fn registry() {
+        let mut registry: PrototypeRegistry = PrototypeRegistry::new();
+        registry.register("ghost_fast", Box::new(Ghost::new(5, 8)));
+        registry.register("ghost_tank", Box::new(Ghost::new(40, 1)));
+        registry.register("demon_lord", Box::new(Demon::new(90, 2)));
+        assert!(registry.names() == vec!["demon_lord", "ghost_fast", "ghost_tank"]);
+
+        let tank = registry.spawn("ghost_tank").unwrap();
+        assert!(tank.health() == 40 && tank.speed() == 1);
+        assert!(registry.spawn("demon_lord").unwrap().health() == 90);
+        assert!(registry.spawn("ghost_boss").err() ==
+                Some(UnknownPrototype("ghost_boss".to_owned())));
+
+        assert!(registry.register("ghost_fast", Box::new(Ghost::new(5, 9))).unwrap().speed() == 8);
+        assert!(registry.get("ghost_fast").unwrap().speed() == 9);
+        assert!(registry.len() == 3);
+
+        // Prototypes of a single concrete type work too.
+        let mut ghosts = PrototypeRegistry::new();
+        ghosts.register("ghost", Ghost::new(1, 1));
+        assert!(ghosts.spawn("ghost").unwrap().health() == 1);
+    }
+}